@@ -4,111 +4,9 @@ use macroquad::prelude::*;
 use quad_snd::{AudioContext, Sound};
 
 use demo_1::panel::*;
-//use demo_1::panel::draw_panel;
-//use demo_1::panel::make_panel;
-use demo_1::font::make_font;
-
-pub struct FontRecord {
-    pub cell_width: u32,
-    pub cell_height: u32,
-}
-
-fn draw_char(c: char, color: Color, x: f32, y: f32, texture: &Texture2D, font_record: &FontRecord)
-{
-    let char_ascii = c as u32;
-
-    let cx = char_ascii % 16;
-    let cy = (char_ascii / 16) - 2;
-    
-    let tx = (font_record.cell_width * cx) as f32;
-    let ty = (font_record.cell_height * cy) as f32;
-
-    draw_texture_ex(
-        &texture,
-        x, y,
-        color,
-        DrawTextureParams {
-            source: Some(Rect{x: tx, y: ty,
-                              w: font_record.cell_width as f32,
-                              h: font_record.cell_height as f32}),
-            ..Default::default()
-        }
-    );
-}
-
-fn draw_string(s: &str, color: Color, x: f32, y: f32, texture: &Texture2D, font_record: &FontRecord)
-{
-    let mut mx = x;
-    
-    for c in s.chars() {
-        draw_char(c, color, mx, y, &texture, &font_record);
-        mx = mx + font_record.cell_width as f32;
-    }
-}
-
-fn draw_box(color: Color, x: f32, y: f32, width: u8, height: u8,
-            texture: &Texture2D, font_record: &FontRecord)
-{
-    for cx in 1..(width-1) {
-        draw_char('-', color,
-                  x + cx as f32 * font_record.cell_width as f32,
-                  y,
-                  &texture, &font_record);
-        draw_char('-', color,
-                  x + cx as f32 * font_record.cell_width as f32,
-                  y + (height - 1) as f32 * font_record.cell_height as f32,
-                  &texture, &font_record);
-    }
-    for cy in 1..(height-1) {
-        draw_char('|', color,
-                  x,
-                  y + cy as f32 * font_record.cell_height as f32,
-                  &texture, &font_record);
-        draw_char('|', color,
-                  x + (width - 1) as f32 * font_record.cell_width as f32,
-                  y + cy as f32 * font_record.cell_height as f32,
-                  &texture, &font_record);
-
-    }
-    draw_char('+', color,
-              x, y,
-              &texture, &font_record);
-                  
-    draw_char('+', color,
-              x + (width - 1) as f32 * font_record.cell_width as f32,
-              y,
-              &texture, &font_record);
-                  
-    draw_char('+', color,
-              x,
-              y + (height - 1) as f32 * font_record.cell_height as f32,
-              &texture, &font_record);
-                  
-    draw_char('+', color,
-              x + (width - 1) as f32 * font_record.cell_width as f32,
-              y + (height - 1) as f32 * font_record.cell_height as f32,
-              &texture, &font_record);
-                  
-        
-}
-
-
-fn write_box(panel: &mut Panel, x: u32, y: u32, width: u32, height: u32)
-{
-    for cx in 1..(width-1) {
-        panel_put_char(panel, '-', x + cx, y);
-        panel_put_char(panel, '-', x + cx, y + (height - 1));
-    }
-    for cy in 1..(height-1) {
-        panel_put_char(panel, '|', x, y + cy);
-        panel_put_char(panel, '|', x + (width - 1), y + cy);
-    }
-    panel_put_char(panel, '+', x, y);
-    panel_put_char(panel, '+', x + (width - 1), y);
-    panel_put_char(panel, '+', x, y + (height - 1));
-    panel_put_char(panel, '+', x + (width - 1), y + (height - 1));
-}
-
+use demo_1::panel::boxes::{draw_box, draw_hline, BorderStyle, BoxOptions};
+use demo_1::font::FontRegistry;
+use demo_1::panel_print;
 
 fn window_conf() -> Conf {
     Conf {
@@ -124,48 +22,78 @@ fn window_conf() -> Conf {
 async fn main() {
     println!("Hello, world!");
 
-    let a2_font: Texture2D = load_texture("assets/40col.png").await.unwrap();
-
-    let a2_font_record = FontRecord{cell_width: 6,
-                                    cell_height: 8};
-
-    a2_font.set_filter(FilterMode::Nearest);
-
     let mut audio_ctx = AudioContext::new();
     
     let beep_sound = Sound::load(&mut audio_ctx, include_bytes!("../../assets/beep.wav"));
     
     beep_sound.play(&mut audio_ctx, Default::default());
 
-    let a2_font_obj = make_font(&a2_font, 6, 8);
+    let fonts = FontRegistry::with_builtins();
+    let font_names: Vec<&str> = fonts.names().collect();
+    let mut font_index = 0;
+
+    let a2_font_obj = fonts.get("apple2-40").unwrap();
     
-    let mut my_panel = make_panel(40.0, 40.0,
-                                  GREEN,
-                                  Some(BLACK),
-                                  2,
-                                  a2_font_obj, 16, 16);
-
-    write_box(&mut my_panel, 0, 0, 16, 16);
-
-    panel_set_cursor_pos(&mut my_panel, 1, 1);
-    panel_write_string(&mut my_panel, "Hello, Panel!");
-
-    let mut panel_2 = make_panel(200.0, 64.0,
-                                 RED,
-                                 Some(BLACK),
-                                 2,
-                                 a2_font_obj, 12, 12);
-    write_box(&mut panel_2, 0, 0, 12, 12);
-    panel_set_cursor_pos(&mut panel_2, 1, 1);
-    panel_write_string(&mut panel_2, "panel 2");
+    let mut my_panel = Panel::builder(a2_font_obj.clone(), 16, 16)
+        .position(40.0, 40.0)
+        .font_color(GREEN)
+        .erase_color(Some(BLACK))
+        .scale(2)
+        .build();
+
+    // the other registered fonts have no box-drawing glyphs
+    draw_box(&mut my_panel, 0, 0, 16, 16, &BoxOptions::new(BorderStyle::Ascii));
+
+    my_panel.set_cursor_pos(1, 1);
+    my_panel.write_string("Hello, Panel!");
+
+    my_panel.set_colors(YELLOW, Some(DARKBLUE));
+    my_panel.set_cursor_pos(1, 14);
+    my_panel.write_string(" F:font B:bell");
+    my_panel.set_colors(GREEN, None);
+
+    my_panel.set_bell(Box::new(move || {
+        beep_sound.play(&audio_ctx, Default::default());
+    }));
+
+    let mut panel_2 = Panel::builder(a2_font_obj, 12, 12)
+        .position(200.0, 64.0)
+        .font_color(RED)
+        .erase_color(Some(BLACK))
+        .scale(2)
+        .build();
+    draw_box(&mut panel_2, 0, 0, 12, 12, &BoxOptions::new(BorderStyle::Double).title("panel 2"));
+    draw_box(&mut panel_2, 2, 2, 5, 3, &BoxOptions::new(BorderStyle::Rounded).shadow(DARKGRAY));
+    panel_2.set_cursor_pos(3, 3);
+    panel_2.write_string("▒▒▒");
+    draw_hline(&mut panel_2, 0, 6, 12, BorderStyle::Single);
+    panel_2.set_cursor_pos(1, 7);
+    panel_2.write_string_attrs("INVERSE", Attrs::INVERSE);
+    panel_2.set_cursor_pos(1, 8);
+    panel_2.write_string_attrs("FLASH", Attrs::FLASH);
+    panel_2.set_cursor_pos(1, 9);
+    panel_2.write_string_attrs("underline", Attrs::UNDERLINE | Attrs::DIM);
 
     let bg_color = Color{r: 0.5, g: 0.7, b: 0.5, a: 1.0};
+    let mut frame_count: u32 = 0;
 
     loop {
+        if is_key_pressed(KeyCode::F) {
+            // cycle the first panel through the registered fonts
+            font_index = (font_index + 1) % font_names.len();
+            my_panel.set_font(fonts.get(font_names[font_index]).unwrap());
+        }
+        if is_key_pressed(KeyCode::B) {
+            my_panel.write_string("\x07");
+        }
+
+        frame_count += 1;
+        panel_print!(&mut panel_2, 1, 10, "F:{:07}", frame_count);
+
         clear_background(bg_color);
 
-        draw_panel(&my_panel);
-        draw_panel(&panel_2);
+        my_panel.draw();
+        panel_2.draw();
         next_frame().await
     }
 }
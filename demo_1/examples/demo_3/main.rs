@@ -6,12 +6,9 @@ use macroquad::prelude::*;
 use quad_snd::{AudioContext, Sound};
 
 use demo_1::panel::*;
-use demo_1::font::make_font;
-
-pub struct FontRecord {
-    pub cell_width: u32,
-    pub cell_height: u32,
-}
+use demo_1::panel::boxes::{draw_box, BorderStyle, BoxOptions};
+use demo_1::font::load_font;
+use demo_1::mouse::{MouseEvent, MouseEventKind, MouseRouter};
 
 #[derive(Debug)]
 pub struct MenuData<'a> {
@@ -99,29 +96,19 @@ impl <'a> MenuData<'a>{
         self.cell_width = max_width;
     }
 
-    fn draw(&self, x: u32, y: u32, scale: u32,
-            texture: &Texture2D, font_record: &FontRecord) {
-        draw_rectangle(x as f32, y as f32,
-                       ((self.cell_width as u32 + 5) * 6 * scale) as f32,
-                       (self.child_keys.len() as u32 * 8 * scale) as f32,
-                       BLACK);
-
-        draw_box(WHITE, x as f32, y as f32,
-                 self.cell_width as u8 + 5,
-                 self.child_keys.len() as u8 + 2,
-                 scale,
-                 texture,
-                 font_record);
-        
+    fn draw(&self, panel: &mut Panel, x: u32, y: u32) {
+        let width = self.cell_width as u32 + 5;
+        let height = self.child_keys.len() as u32 + 2;
 
-        // TODO push font info into font object
+        panel.set_colors(WHITE, Some(BLACK));
+        panel.fill(panel.blank_cell(), x, y, width, height);
+        draw_box(panel, x, y, width, height,
+                 &BoxOptions::new(BorderStyle::Single).title(self.name));
 
         // TODO use is_active to gray out non-top menu panels
 
         // TODO add menu colors
 
-        // TODO move all of this into a panel
-
         // TODO handle two+ column
 
         // TODO handle scrolling
@@ -130,33 +117,24 @@ impl <'a> MenuData<'a>{
         // draw items
         for i in 0 .. self.child_keys.len() {
             let cname = self.child_keys[i];
-            draw_string(cname, WHITE,
-                        x as f32 + (6 * 2) as f32 * scale as f32,
-                        (y + (8 * (i + 1) as u32 * scale)) as f32,
-                        2,
-                        texture, font_record);
+            let row = y + 1 + i as u32;
+            panel.set_colors(WHITE, Some(BLACK));
+            panel.set_cursor_pos(x + 2, row);
+            panel.write_string(cname);
 
             let cmo = &self.child_menus[cname];
             if !cmo.is_leaf {
-                draw_char('>', GRAY,
-                          x as f32 + (6 * (self.cell_width + 3)) as f32 * scale as f32,
-                          (y + (8 * (i + 1) as u32 * scale)) as f32,
-                          2,
-                          texture, font_record);                          
+                panel.set_colors(GRAY, Some(BLACK));
+                panel.put_char('>', x + self.cell_width as u32 + 3, row);
             }
         }
 
         // draw cursor
 
-        draw_char('>', RED,
-                  x as f32 +
-                  (1.0 + (self.cursor_x as f32 * (self.cell_width + 3) as f32)) *
-                  6.0 * scale as f32,
-                  y as f32 +
-                  (self.cursor_y + 1) as f32 *
-                  (8 * scale) as f32,
-                  2,
-                  texture, font_record);
+        panel.set_colors(RED, Some(BLACK));
+        panel.put_char('>',
+                       x + 1 + self.cursor_x as u32 * (self.cell_width as u32 + 3),
+                       y + 1 + self.cursor_y as u32);
 
         // TODO draw up/down prompts
     }
@@ -167,8 +145,6 @@ impl <'a> MenuData<'a>{
     }
 
     fn on_up(&mut self) {
-        println!("on up");
-
         match self.cursor_y.checked_sub(1) {
             Some(ny) => {
                 self.cursor_y = ny;
@@ -179,8 +155,6 @@ impl <'a> MenuData<'a>{
     }
 
     fn on_down(&mut self) {
-        println!("on down");
-
         let old_y = self.cursor_y;
 
         self.cursor_y += 1;
@@ -212,27 +186,35 @@ impl <'a> MenuData<'a>{
         }
     }
 
-    fn on_select(&mut self) {
-        // TODO possibly return an event?
-        // possibly indicate a new menu should be added?
-
-        println!("Selecting a thing??");
-        let idx = self.get_selected_index();
-        println!("selection index: {0}", idx);
-        let child_name = self.child_keys[idx];
-        println!("selection name: {0}", child_name);
-        let child_menu = &self.child_menus[child_name];
-        if child_menu.is_leaf {
-            println!("child is leaf");
-        } else {
-            println!("child is not leaf");
-        }
+    // returns the id of the selected child
+    fn on_select(&mut self) -> Option<i32> {
+        // TODO possibly indicate a new menu should be added?
+        let key = self.child_keys.get(self.get_selected_index())?;
+        Some(self.child_menus[key].id)
     }
 
     fn on_cancel(&mut self) {
         // TODO is there cleanup to be done here?
     }
 
+    // moves the cursor to the item at panel cell cx, cy, with the menu
+    // drawn at x, y; returns false if there's no item there
+    fn on_point(&mut self, x: u32, y: u32, cx: u32, cy: u32) -> bool {
+        let width = self.cell_width as u32 + 5;
+        if cx <= x || cx + 1 >= x + width || cy <= y {
+            return false;
+        }
+
+        let index = (cy - y - 1) as usize;
+        let cols = self.viz_width.max(1);
+        if index >= self.child_keys.len() || index / cols >= self.viz_height {
+            return false;
+        }
+        self.cursor_x = index % cols;
+        self.cursor_y = index / cols;
+        true
+    }
+
     fn new(new_name: &'a str, id: i32) -> MenuData<'a> {
         let md = MenuData {
             name: new_name,
@@ -254,18 +236,14 @@ impl <'a> MenuData<'a>{
 
 pub struct MenuManager<'a> {
     menu_stack: Vec<&'a mut MenuData<'a>>,
-    texture: &'a Texture2D,
-    font_record: &'a FontRecord,
-    font_scale: u32,
+    panel: Panel,
 }
 
 impl <'a> MenuManager<'a> {
-    fn new(scale: u32, texture: &'a Texture2D, font_record: &'a FontRecord) -> MenuManager<'a> {
+    fn new(panel: Panel) -> MenuManager<'a> {
         let mm = MenuManager {
             menu_stack: vec!(),
-            texture: texture,
-            font_record: font_record,
-            font_scale: scale,
+            panel: panel,
         };
         mm
     }
@@ -275,18 +253,21 @@ impl <'a> MenuManager<'a> {
         self.menu_stack.push(menu_object);
     }
 
-    fn draw(&self, x: u32, y: u32) {
-        let mut tx = x;
-        let mut ty = y;
+    fn draw(&mut self) {
+        let mut tx = 0;
+        let mut ty = 0;
 
-        let x_spacing = 12;
-        let y_spacing = 8;
-        
+        let x_spacing = 1;
+        let y_spacing = 1;
+
+        self.panel.set_colors(WHITE, None);
+        self.panel.clear();
         for m in self.menu_stack.iter() {
-            m.draw(tx, ty, self.font_scale, self.texture, self.font_record);
+            m.draw(&mut self.panel, tx, ty);
             tx = tx + x_spacing;
             ty = ty + y_spacing;
         }
+        self.panel.draw();
     }
 
     fn get_top_menu(&mut self) -> Result<&mut MenuData<'a>, String>
@@ -305,15 +286,11 @@ impl <'a> MenuManager<'a> {
     }
 
     fn on_up(&mut self) {
-        println!("up");
-
         let mut md = self.get_top_menu().unwrap();
         md.on_up();
     }
 
     fn on_down(&mut self) {
-        println!("down");
-        
         let mut md = self.get_top_menu().unwrap();
         md.on_down();
     }
@@ -328,11 +305,11 @@ impl <'a> MenuManager<'a> {
         md.on_right();
     }
 
-    fn on_select(&mut self) {
-        let mut md = self.get_top_menu().unwrap();
-        md.on_select();
+    fn on_select(&mut self) -> Option<i32> {
+        let md = self.get_top_menu().unwrap();
 
         // might also need to push a new menu
+        md.on_select()
     }
 
     fn on_cancel(&mut self) {
@@ -341,118 +318,30 @@ impl <'a> MenuManager<'a> {
 
         // probably need to pop a menu
     }
-}
 
-fn draw_char(c: char, color: Color, x: f32, y: f32, scale: u32,
-             texture: &Texture2D, font_record: &FontRecord)
-{
-    let char_ascii = c as u32;
+    fn on_mouse(&mut self, event: MouseEvent) -> Option<i32> {
+        // each menu is drawn one cell down and right of the one below it
+        let depth = self.menu_stack.len().saturating_sub(1) as u32;
+        let md = self.get_top_menu().unwrap();
 
-    let cx = char_ascii % 16;
-    let cy = (char_ascii / 16) - 2;
-    
-    let tx = (font_record.cell_width * cx) as f32;
-    let ty = (font_record.cell_height * cy) as f32;
-
-    draw_texture_ex(
-        &texture,
-        x, y,
-        color,
-        DrawTextureParams {
-            source: Some(Rect{x: tx, y: ty,
-                              w: font_record.cell_width as f32,
-                              h: font_record.cell_height as f32}),
-            dest_size: Some(Vec2{
-                x: (font_record.cell_width * scale) as f32,
-                y: (font_record.cell_height * scale) as f32}),
-            ..Default::default()
+        match event.kind {
+            MouseEventKind::Hover => {
+                md.on_point(depth, depth, event.x, event.y);
+            }
+            MouseEventKind::Click(MouseButton::Left) => {
+                if md.on_point(depth, depth, event.x, event.y) {
+                    return md.on_select();
+                }
+            }
+            MouseEventKind::Click(MouseButton::Right) => md.on_cancel(),
+            MouseEventKind::Wheel(_, dy) if dy > 0.0 => md.on_up(),
+            MouseEventKind::Wheel(_, dy) if dy < 0.0 => md.on_down(),
+            _ => {}
         }
-    );
-}
-
-fn draw_string(s: &str, color: Color, x: f32, y: f32,
-               scale: u32, texture: &Texture2D, font_record: &FontRecord)
-{
-    let mut mx = x;
-    
-    for c in s.chars() {
-        draw_char(c, color, mx, y, scale, &texture, &font_record);
-        mx = mx + (font_record.cell_width * scale) as f32;
-    }
-}
-
-fn draw_box(color: Color, x: f32, y: f32,
-            width: u8, height: u8,
-            scale: u32,
-            texture: &Texture2D, font_record: &FontRecord)
-{
-    for cx in 1..(width-1) {
-        draw_char('-', color,
-                  x + cx as f32 * font_record.cell_width as f32 * scale as f32,
-                  y,
-                  scale,
-                  &texture, &font_record);
-        draw_char('-', color,
-                  x + cx as f32 * font_record.cell_width as f32 * scale as f32,
-                  y + (height - 1) as f32 * font_record.cell_height as f32 * scale as f32,
-                  scale,
-                  &texture, &font_record);
-    }
-    for cy in 1..(height-1) {
-        draw_char('|', color,
-                  x,
-                  y + cy as f32 * font_record.cell_height as f32 * scale as f32,
-                  scale,
-                  &texture, &font_record);
-        draw_char('|', color,
-                  x + (width - 1) as f32 * font_record.cell_width as f32 * scale as f32,
-                  y + cy as f32 * font_record.cell_height as f32 * scale as f32,
-                  scale,
-                  &texture, &font_record);
-
-    }
-    draw_char('+', color,
-              x, y,
-              scale,
-              &texture, &font_record);
-                  
-    draw_char('+', color,
-              x + (width - 1) as f32 * font_record.cell_width as f32 * scale as f32,
-              y,
-              scale,
-              &texture, &font_record);
-                  
-    draw_char('+', color,
-              x,
-              y + (height - 1) as f32 * font_record.cell_height as f32 * scale as f32,
-              scale,
-              &texture, &font_record);
-                  
-    draw_char('+', color,
-              x + (width - 1) as f32 * font_record.cell_width as f32 * scale as f32,
-              y + (height - 1) as f32 * font_record.cell_height as f32 * scale as f32,
-              scale,
-              &texture, &font_record);
-}
-
-
-fn write_box(panel: &mut Panel, x: u32, y: u32, width: u32, height: u32)
-{
-    for cx in 1..(width-1) {
-        panel_put_char(panel, '-', x + cx, y);
-        panel_put_char(panel, '-', x + cx, y + (height - 1));
-    }
-    for cy in 1..(height-1) {
-        panel_put_char(panel, '|', x, y + cy);
-        panel_put_char(panel, '|', x + (width - 1), y + cy);
+        None
     }
-    panel_put_char(panel, '+', x, y);
-    panel_put_char(panel, '+', x + (width - 1), y);
-    panel_put_char(panel, '+', x, y + (height - 1));
-    panel_put_char(panel, '+', x + (width - 1), y + (height - 1));
 }
 
-
 fn window_conf() -> Conf {
     Conf {
         window_title: "Demo3: menu".to_owned(),
@@ -467,41 +356,53 @@ fn window_conf() -> Conf {
 async fn main() {
     println!("Hello, world!");
 
-    let a2_font: Texture2D = load_texture("assets/40col.png").await.unwrap();
-
-    let a2_font_record = FontRecord{cell_width: 6,
-                                    cell_height: 8};
-
-    a2_font.set_filter(FilterMode::Nearest);
-
     let mut audio_ctx = AudioContext::new();
     
     let beep_sound = Sound::load(&mut audio_ctx, include_bytes!("../../assets/beep.wav"));
     
     beep_sound.play(&mut audio_ctx, Default::default());
 
-    let a2_font_obj = make_font(&a2_font, 6, 8);
+    let mut a2_font_obj = load_font("assets/40col").await.unwrap();
+    a2_font_obj.add_pages_from(&load_font("assets/40col_hi").await.unwrap()).unwrap();
     
-    let mut my_panel = make_panel(40.0, 40.0,
-                                  GREEN,
-                                  Some(BLACK),
-                                  2,
-                                  a2_font_obj, 16, 16);
-
-    write_box(&mut my_panel, 0, 0, 16, 16);
-
-    panel_set_cursor_pos(&mut my_panel, 1, 1);
-    panel_write_string(&mut my_panel, "Hello, Panel!");
+    // my_panel is drawn in palette entry 1 on entry 0, so the settings
+    // menu can recolor it by swapping in another two color palette, picked
+    // from one of the preset palettes
+    let presets = Palette::presets();
+    let two_colors = |preset: usize, fg: usize, bg: usize| {
+        let colors = &presets[preset].1.colors;
+        Palette::new(vec![colors[bg % colors.len()], colors[fg % colors.len()]])
+    };
+    let mut preset = 0;
+    let mut font_color = 12;
+    let mut back_color = 0;
+
+    let mut my_panel = Panel::builder(a2_font_obj.clone(), 16, 16)
+        .position(40.0, 40.0)
+        .erase_color(Some(BLACK))
+        .palette(two_colors(preset, font_color, back_color))
+        .scale(2)
+        .build();
+    my_panel.set_palette_colors(1, Some(0));
+    my_panel.clear();
+
+    draw_box(&mut my_panel, 0, 0, 16, 16, &BoxOptions::new(BorderStyle::Single));
+
+    my_panel.set_cursor_pos(1, 1);
+    my_panel.write_string("Hello, Panel!");
+    my_panel.set_cursor_pos(1, 3);
+    my_panel.write_string(presets[preset].0);
 
     /*
-    let mut panel_2 = make_panel(200.0, 64.0,
-                                 RED,
-                                 Some(BLACK),
-                                 2,
-                                 a2_font_obj, 12, 12);
-    write_box(&mut panel_2, 0, 0, 12, 12);
-    panel_set_cursor_pos(&mut panel_2, 1, 1);
-    panel_write_string(&mut panel_2, "panel 2");
+    let mut panel_2 = Panel::builder(a2_font_obj, 12, 12)
+        .position(200.0, 64.0)
+        .font_color(RED)
+        .erase_color(Some(BLACK))
+        .scale(2)
+        .build();
+    draw_box(&mut panel_2, 0, 0, 12, 12, &BoxOptions::new(BorderStyle::Single));
+    panel_2.set_cursor_pos(1, 1);
+    panel_2.write_string("panel 2");
      */
     let bg_color = Color{r: 0.5, g: 0.7, b: 0.5, a: 1.0};
 
@@ -516,6 +417,7 @@ async fn main() {
     settings.add_child(MenuData::new("font color", 1001));
     settings.add_child(MenuData::new("background color", 1002));
     settings.add_child(MenuData::new("overscan color", 1003));
+    settings.add_child(MenuData::new("palette", 1004));
     
     let demos = root_menu_obj.get_mut_child("demos");
     demos.add_child(MenuData::new("mandelbrot", 2001));
@@ -581,11 +483,26 @@ async fn main() {
 
     root_menu_obj.build();
 
-    let mut my_menu_mgr = MenuManager::new(2, &a2_font, &a2_font_record);
+    let menu_panel = Panel::builder(a2_font_obj, 60, 40)
+        .position(50.0, 50.0)
+        .scale(2)
+        .build();
+    let mut my_menu_mgr = MenuManager::new(menu_panel);
 
     my_menu_mgr.open(&mut root_menu_obj);
 
+    let mut mouse = MouseRouter::new();
+
     loop {
+        let mut selected = None;
+
+        let events = mouse.poll(&[&my_panel, &my_menu_mgr.panel]);
+        for event in events {
+            if event.panel == 1 {
+                selected = selected.or(my_menu_mgr.on_mouse(event));
+            }
+        }
+
         if is_key_pressed(KeyCode::Up) {
             my_menu_mgr.on_up();
         }
@@ -599,19 +516,49 @@ async fn main() {
             my_menu_mgr.on_right();
         }
         if is_key_pressed(KeyCode::Space) {
-            my_menu_mgr.on_select();
+            selected = selected.or(my_menu_mgr.on_select());
         }
         if is_key_pressed(KeyCode::Escape) {
             my_menu_mgr.on_cancel();
         }
+
+        // shortcuts for the settings items, until submenus can be opened
+        if is_key_pressed(KeyCode::F) {
+            selected = selected.or(Some(1001));
+        }
+        if is_key_pressed(KeyCode::B) {
+            selected = selected.or(Some(1002));
+        }
+        if is_key_pressed(KeyCode::P) {
+            selected = selected.or(Some(1004));
+        }
+
+        let preset_size = presets[preset].1.colors.len();
+        match selected {
+            Some(1001) => {
+                font_color = (font_color + 1) % preset_size;
+                my_panel.set_palette(two_colors(preset, font_color, back_color));
+            }
+            Some(1002) => {
+                back_color = (back_color + 1) % preset_size;
+                my_panel.set_palette(two_colors(preset, font_color, back_color));
+            }
+            Some(1004) => {
+                preset = (preset + 1) % presets.len();
+                my_panel.set_palette(two_colors(preset, font_color, back_color));
+                my_panel.set_cursor_pos(1, 3);
+                my_panel.write_string(&format!("{:<14}", presets[preset].0));
+            }
+            _ => {}
+        }
         
         
         
         clear_background(bg_color);
 
-        draw_panel(&my_panel);
-        //draw_panel(&panel_2);
-        my_menu_mgr.draw(50, 50);
+        my_panel.draw();
+        //panel_2.draw();
+        my_menu_mgr.draw();
         next_frame().await
     }
 }
@@ -0,0 +1,639 @@
+//! A streaming VT100/ANSI escape sequence interpreter that writes into a
+//! `Panel`.
+//!
+//! Feed it output from other programs a chunk at a time; sequences and
+//! UTF-8 characters split between chunks are held until they complete.
+//! Plain text and control characters go through `Panel::write_string`, so
+//! the panel's wrapping, tab stops and bell apply. Programs writing to a
+//! terminal send CR LF, so turn off the panel's `lf_returns` when feeding
+//! it their output.
+
+use macroquad::prelude::*;
+
+use crate::panel::*;
+
+/// Longest parameter list kept from a CSI sequence; extra ones are dropped.
+const MAX_PARAMS: usize = 16;
+
+#[derive(Debug, Copy, Clone, PartialEq)]
+enum State {
+    Ground,
+    Escape,
+    /// After `ESC` plus an intermediate byte such as `(`; the next byte
+    /// ends the sequence and is ignored.
+    EscapeIntermediate,
+    Csi,
+    /// Operating system command, skipped up to BEL or ST.
+    Osc,
+    OscEscape,
+}
+
+/// The cursor state saved by `ESC 7` or `CSI s`.
+#[derive(Debug, Copy, Clone)]
+struct SavedCursor {
+    x: u32,
+    y: u32,
+    fg: CellColor,
+    bg: Option<CellColor>,
+    attrs: Attrs,
+    bold: bool,
+    fg_index: Option<usize>,
+}
+
+#[derive(Debug, Clone)]
+pub struct AnsiParser {
+    state: State,
+    params: Vec<u32>,
+    param_started: bool,
+    private: bool,
+    utf8: Vec<u8>,
+
+    /// Colors for SGR 0, 39 and 49.
+    pub default_fg: Color,
+    pub default_bg: Option<Color>,
+
+    bold: bool,
+    /// The standard color last picked with SGR 30 to 37, which bold
+    /// shows in its bright version.
+    fg_index: Option<usize>,
+    saved: Option<SavedCursor>,
+}
+
+/// The standard 16 terminal colors, as xterm draws them.
+pub const ANSI_COLORS: [Color; 16] = [
+    Color::new(0.0, 0.0, 0.0, 1.0),
+    Color::new(0.804, 0.0, 0.0, 1.0),
+    Color::new(0.0, 0.804, 0.0, 1.0),
+    Color::new(0.804, 0.804, 0.0, 1.0),
+    Color::new(0.0, 0.0, 0.933, 1.0),
+    Color::new(0.804, 0.0, 0.804, 1.0),
+    Color::new(0.0, 0.804, 0.804, 1.0),
+    Color::new(0.898, 0.898, 0.898, 1.0),
+    Color::new(0.498, 0.498, 0.498, 1.0),
+    Color::new(1.0, 0.0, 0.0, 1.0),
+    Color::new(0.0, 1.0, 0.0, 1.0),
+    Color::new(1.0, 1.0, 0.0, 1.0),
+    Color::new(0.361, 0.361, 1.0, 1.0),
+    Color::new(1.0, 0.0, 1.0, 1.0),
+    Color::new(0.0, 1.0, 1.0, 1.0),
+    Color::new(1.0, 1.0, 1.0, 1.0),
+];
+
+/// Color `index` of the xterm 256 color palette: the 16 standard colors,
+/// a 6x6x6 color cube, then 24 grays.
+pub fn ansi_256_color(index: u8) -> Color
+{
+    match index {
+        0..=15 => ANSI_COLORS[index as usize],
+        16..=231 => {
+            let i = index - 16;
+            let level = |v: u8| if v == 0 { 0.0 } else { (55.0 + 40.0 * v as f32) / 255.0 };
+            Color::new(level(i / 36), level((i / 6) % 6), level(i % 6), 1.0)
+        }
+        _ => {
+            let gray = (8.0 + 10.0 * (index - 232) as f32) / 255.0;
+            Color::new(gray, gray, gray, 1.0)
+        }
+    }
+}
+
+impl AnsiParser {
+    pub fn new(default_fg: Color, default_bg: Option<Color>) -> AnsiParser
+    {
+        AnsiParser {
+            state: State::Ground,
+            params: vec![],
+            param_started: false,
+            private: false,
+            utf8: vec![],
+            default_fg,
+            default_bg,
+            bold: false,
+            fg_index: None,
+            saved: None,
+        }
+    }
+
+    /// Interprets `s` into `panel`.
+    pub fn write_str(&mut self, panel: &mut Panel, s: &str)
+    {
+        self.write(panel, s.as_bytes());
+    }
+
+    /// Interprets `bytes` into `panel`. `bytes` may end partway through an
+    /// escape sequence or UTF-8 character; the rest is expected next call.
+    pub fn write(&mut self, panel: &mut Panel, bytes: &[u8])
+    {
+        for &b in bytes {
+            self.byte(panel, b);
+        }
+    }
+
+    fn byte(&mut self, panel: &mut Panel, b: u8)
+    {
+        match self.state {
+            State::Ground => self.ground(panel, b),
+            State::Escape => self.escape(panel, b),
+            State::EscapeIntermediate => self.state = State::Ground,
+            State::Csi => self.csi(panel, b),
+            State::Osc => match b {
+                0x07 => self.state = State::Ground,
+                0x1b => self.state = State::OscEscape,
+                _ => {}
+            },
+            State::OscEscape => {
+                // ST is ESC \; anything else also ends the string
+                self.state = if b == b'\\' { State::Ground } else { State::Osc };
+            }
+        }
+    }
+
+    fn ground(&mut self, panel: &mut Panel, b: u8)
+    {
+        if !self.utf8.is_empty() || b >= 0x80 {
+            self.utf8_byte(panel, b);
+            return;
+        }
+        match b {
+            0x1b => self.state = State::Escape,
+            _ => {
+                let c = b as char;
+                panel.write_string(c.encode_utf8(&mut [0; 4]));
+            }
+        }
+    }
+
+    fn utf8_byte(&mut self, panel: &mut Panel, b: u8)
+    {
+        let continuation = b & 0xc0 == 0x80;
+        if !self.utf8.is_empty() && !continuation {
+            // the previous character was cut short
+            self.utf8.clear();
+            panel.write_string("\u{fffd}");
+            self.ground(panel, b);
+            return;
+        }
+
+        self.utf8.push(b);
+        let expected = match self.utf8[0] {
+            0xc0..=0xdf => 2,
+            0xe0..=0xef => 3,
+            0xf0..=0xf7 => 4,
+            _ => 1,
+        };
+        if self.utf8.len() < expected {
+            return;
+        }
+        let s = std::str::from_utf8(&self.utf8).unwrap_or("\u{fffd}").to_string();
+        self.utf8.clear();
+        panel.write_string(&s);
+    }
+
+    fn escape(&mut self, panel: &mut Panel, b: u8)
+    {
+        self.state = State::Ground;
+        match b {
+            b'[' => {
+                self.params.clear();
+                self.param_started = false;
+                self.private = false;
+                self.state = State::Csi;
+            }
+            b']' | b'P' | b'_' | b'^' => self.state = State::Osc,
+            b'(' | b')' | b'*' | b'+' | b'#' | b'%' => self.state = State::EscapeIntermediate,
+            b'7' => self.save_cursor(panel),
+            b'8' => self.restore_cursor(panel),
+            b'D' => panel.line_feed(),
+            b'E' => {
+                panel.cursor_x = 0;
+                panel.line_feed();
+            }
+            b'M' => reverse_index(panel),
+            b'c' => self.reset(panel),
+            _ => {}
+        }
+    }
+
+    fn csi(&mut self, panel: &mut Panel, b: u8)
+    {
+        match b {
+            b'0'..=b'9' => {
+                if !self.param_started {
+                    self.params.push(0);
+                    self.param_started = true;
+                }
+                if let Some(p) = self.params.last_mut() {
+                    *p = p.saturating_mul(10).saturating_add((b - b'0') as u32);
+                }
+            }
+            b';' | b':' => {
+                if !self.param_started {
+                    self.params.push(0);
+                }
+                self.param_started = false;
+            }
+            b'?' | b'>' | b'=' | b'<' => self.private = true,
+            0x20..=0x2f => {}
+            0x40..=0x7e => {
+                self.params.truncate(MAX_PARAMS);
+                self.state = State::Ground;
+                self.dispatch(panel, b);
+            }
+            0x1b => self.state = State::Escape,
+            0x18 | 0x1a => self.state = State::Ground,
+            _ => {
+                // C0 controls still take effect in the middle of a sequence
+                let c = b as char;
+                panel.write_string(c.encode_utf8(&mut [0; 4]));
+            }
+        }
+    }
+
+    /// Parameter `i`, with 0 or a missing value meaning `default`.
+    fn param(&self, i: usize, default: u32) -> u32
+    {
+        match self.params.get(i) {
+            Some(&p) if p != 0 => p,
+            _ => default,
+        }
+    }
+
+    fn dispatch(&mut self, panel: &mut Panel, command: u8)
+    {
+        let max_x = panel.char_width.saturating_sub(1);
+        let max_y = panel.char_height.saturating_sub(1);
+        let n = self.param(0, 1);
+        let x = panel.cursor_x.min(max_x);
+        let y = panel.cursor_y.min(max_y);
+
+        if self.private {
+            if command == b'h' || command == b'l' {
+                let on = command == b'h';
+                for &mode in &self.params {
+                    match mode {
+                        7 => panel.set_wrap(on),
+                        25 => panel.show_cursor(on),
+                        _ => {}
+                    }
+                }
+            }
+            return;
+        }
+
+        match command {
+            b'A' => panel.set_cursor_pos(x, y.saturating_sub(n)),
+            b'B' | b'e' => panel.set_cursor_pos(x, y.saturating_add(n).min(max_y)),
+            b'C' | b'a' => panel.set_cursor_pos(x.saturating_add(n).min(max_x), y),
+            b'D' => panel.set_cursor_pos(x.saturating_sub(n), y),
+            b'E' => panel.set_cursor_pos(0, y.saturating_add(n).min(max_y)),
+            b'F' => panel.set_cursor_pos(0, y.saturating_sub(n)),
+            b'G' | b'`' => panel.set_cursor_pos((n - 1).min(max_x), y),
+            b'd' => panel.set_cursor_pos(x, (n - 1).min(max_y)),
+            b'H' | b'f' => {
+                let row = self.param(0, 1) - 1;
+                let col = self.param(1, 1) - 1;
+                panel.set_cursor_pos(col.min(max_x), row.min(max_y));
+            }
+            b'J' => self.erase_in_display(panel, x, y),
+            b'K' => self.erase_in_line(panel, x, y),
+            b'X' => erase_cells(panel, y, x, x.saturating_add(n).min(panel.char_width)),
+            b'@' => shift_row(panel, x, y, n, true),
+            b'P' => shift_row(panel, x, y, n, false),
+            b'L' => scroll_from_cursor(panel, y, n, true),
+            b'M' => scroll_from_cursor(panel, y, n, false),
+            b'S' => panel.scroll_up(n),
+            b'T' => panel.scroll_down(n),
+            b'm' => self.select_graphic_rendition(panel),
+            b'r' => {
+                let top = self.param(0, 1) - 1;
+                let bottom = self.param(1, panel.char_height).saturating_sub(1).min(max_y);
+                panel.set_scroll_region(top, bottom);
+                panel.set_cursor_pos(0, 0);
+            }
+            b's' => self.save_cursor(panel),
+            b'u' => self.restore_cursor(panel),
+            _ => {}
+        }
+    }
+
+    fn erase_in_display(&self, panel: &mut Panel, x: u32, y: u32)
+    {
+        match self.params.first().copied().unwrap_or(0) {
+            0 => {
+                erase_cells(panel, y, x, panel.char_width);
+                for row in y + 1..panel.char_height {
+                    erase_cells(panel, row, 0, panel.char_width);
+                }
+            }
+            1 => {
+                for row in 0..y {
+                    erase_cells(panel, row, 0, panel.char_width);
+                }
+                erase_cells(panel, y, 0, x + 1);
+            }
+            2 | 3 => panel.clear(),
+            _ => {}
+        }
+    }
+
+    fn erase_in_line(&self, panel: &mut Panel, x: u32, y: u32)
+    {
+        match self.params.first().copied().unwrap_or(0) {
+            0 => erase_cells(panel, y, x, panel.char_width),
+            1 => erase_cells(panel, y, 0, x + 1),
+            2 => erase_cells(panel, y, 0, panel.char_width),
+            _ => {}
+        }
+    }
+
+    fn select_graphic_rendition(&mut self, panel: &mut Panel)
+    {
+        if self.params.is_empty() {
+            self.params.push(0);
+        }
+
+        let mut i = 0;
+        while i < self.params.len() {
+            let p = self.params[i];
+            match p {
+                0 => {
+                    self.bold = false;
+                    self.fg_index = None;
+                    panel.set_colors(self.default_fg, self.default_bg);
+                    panel.set_attrs(Attrs::NONE);
+                }
+                1 => {
+                    self.bold = true;
+                    self.set_fg_index(panel, self.fg_index);
+                }
+                2 => panel.attrs.insert(Attrs::DIM),
+                4 => panel.attrs.insert(Attrs::UNDERLINE),
+                5 | 6 => panel.attrs.insert(Attrs::FLASH),
+                7 => panel.attrs.insert(Attrs::INVERSE),
+                22 => {
+                    self.bold = false;
+                    self.set_fg_index(panel, self.fg_index);
+                    panel.attrs.remove(Attrs::DIM);
+                }
+                24 => panel.attrs.remove(Attrs::UNDERLINE),
+                25 => panel.attrs.remove(Attrs::FLASH),
+                27 => panel.attrs.remove(Attrs::INVERSE),
+                30..=37 => self.set_fg_index(panel, Some((p - 30) as usize)),
+                39 => {
+                    self.fg_index = None;
+                    panel.font_color = self.default_fg.into();
+                }
+                40..=47 => panel.back_color = Some(ANSI_COLORS[(p - 40) as usize].into()),
+                49 => panel.back_color = self.default_bg.map(CellColor::from),
+                90..=97 => {
+                    self.fg_index = None;
+                    panel.font_color = ANSI_COLORS[(p - 90) as usize + 8].into();
+                }
+                100..=107 => panel.back_color = Some(ANSI_COLORS[(p - 100) as usize + 8].into()),
+                38 | 48 => {
+                    let (color, used) = self.extended_color(i + 1);
+                    if let Some(color) = color {
+                        if p == 38 {
+                            self.fg_index = None;
+                            panel.font_color = color.into();
+                        } else {
+                            panel.back_color = Some(color.into());
+                        }
+                    }
+                    i += used;
+                }
+                _ => {}
+            }
+            i += 1;
+        }
+    }
+
+    /// Sets the foreground to standard color `index`, bright if bold. With
+    /// no index the foreground is left alone.
+    fn set_fg_index(&mut self, panel: &mut Panel, index: Option<usize>)
+    {
+        self.fg_index = index;
+        if let Some(index) = index {
+            panel.font_color = ANSI_COLORS[index + if self.bold { 8 } else { 0 }].into();
+        }
+    }
+
+    /// Reads a `5;n` or `2;r;g;b` color starting at parameter `i`. Returns
+    /// the color and how many parameters it used.
+    fn extended_color(&self, i: usize) -> (Option<Color>, usize)
+    {
+        let get = |j: usize| self.params.get(j).copied();
+        match get(i) {
+            Some(5) => match get(i + 1) {
+                Some(n) => (Some(ansi_256_color(n.min(255) as u8)), 2),
+                None => (None, 1),
+            },
+            Some(2) => match (get(i + 1), get(i + 2), get(i + 3)) {
+                (Some(r), Some(g), Some(b)) => {
+                    let c = |v: u32| v.min(255) as f32 / 255.0;
+                    (Some(Color::new(c(r), c(g), c(b), 1.0)), 4)
+                }
+                _ => (None, self.params.len() - i),
+            },
+            _ => (None, 0),
+        }
+    }
+
+    fn save_cursor(&mut self, panel: &Panel)
+    {
+        self.saved = Some(SavedCursor {
+            x: panel.cursor_x,
+            y: panel.cursor_y,
+            fg: panel.font_color,
+            bg: panel.back_color,
+            attrs: panel.attrs,
+            bold: self.bold,
+            fg_index: self.fg_index,
+        });
+    }
+
+    fn restore_cursor(&mut self, panel: &mut Panel)
+    {
+        let Some(saved) = self.saved else {
+            panel.set_cursor_pos(0, 0);
+            return;
+        };
+        panel.set_cursor_pos(saved.x.min(panel.char_width),
+                             saved.y.min(panel.char_height.saturating_sub(1)));
+        panel.font_color = saved.fg;
+        panel.back_color = saved.bg;
+        panel.set_attrs(saved.attrs);
+        self.bold = saved.bold;
+        self.fg_index = saved.fg_index;
+    }
+
+    fn reset(&mut self, panel: &mut Panel)
+    {
+        self.bold = false;
+        self.fg_index = None;
+        self.saved = None;
+        panel.set_colors(self.default_fg, self.default_bg);
+        panel.set_attrs(Attrs::NONE);
+        panel.set_scroll_region(0, panel.char_height.saturating_sub(1));
+        panel.set_wrap(true);
+        panel.clear();
+        panel.set_cursor_pos(0, 0);
+    }
+}
+
+/// Blanks columns `from` up to but not including `to` of `row` in the
+/// current colors.
+fn erase_cells(panel: &mut Panel, row: u32, from: u32, to: u32)
+{
+    let blank = panel.blank_cell();
+    let to = to.min(panel.char_width);
+    if let Some(cells) = panel.cells.get_mut(row as usize) {
+        for cell in &mut cells[from.min(to) as usize..to as usize] {
+            *cell = blank;
+        }
+    }
+}
+
+/// Inserts (or deletes) `n` blank cells at the cursor, shifting the rest of
+/// the row right (or left).
+fn shift_row(panel: &mut Panel, x: u32, y: u32, n: u32, insert: bool)
+{
+    let blank = panel.blank_cell();
+    let Some(cells) = panel.cells.get_mut(y as usize) else {
+        return;
+    };
+    let tail = &mut cells[x as usize..];
+    let n = (n as usize).min(tail.len());
+    if insert {
+        tail.rotate_right(n);
+        tail[..n].fill(blank);
+    } else {
+        tail.rotate_left(n);
+        let len = tail.len();
+        tail[len - n..].fill(blank);
+    }
+}
+
+/// Inserts (or deletes) `n` lines at row `y` by scrolling the part of the
+/// scroll region from `y` down.
+fn scroll_from_cursor(panel: &mut Panel, y: u32, n: u32, insert: bool)
+{
+    if y < panel.scroll_top || y > panel.scroll_bottom {
+        return;
+    }
+    let top = panel.scroll_top;
+    panel.scroll_top = y;
+    if insert {
+        panel.scroll_down(n);
+    } else {
+        panel.scroll_up(n);
+    }
+    panel.scroll_top = top;
+    panel.cursor_x = 0;
+}
+
+fn reverse_index(panel: &mut Panel)
+{
+    if panel.cursor_y == panel.scroll_top {
+        panel.scroll_down(1);
+    } else if panel.cursor_y > 0 {
+        panel.cursor_y -= 1;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::font::make_font;
+    use crate::font::tests::texture;
+
+    fn panel(w: u32, h: u32) -> Panel
+    {
+        Panel::builder(make_font(&texture(), 6, 8), w, h).font_color(WHITE).build()
+    }
+
+    fn parser() -> AnsiParser
+    {
+        AnsiParser::new(WHITE, None)
+    }
+
+    fn text(panel: &Panel) -> String
+    {
+        let rows: Vec<String> = panel.cells.iter()
+            .map(|row| row.iter().map(|cell| cell.ch).collect::<String>().trim_end().to_string())
+            .collect();
+        rows.join("\n")
+    }
+
+    fn fg(panel: &Panel, x: usize, y: usize) -> CellColor
+    {
+        panel.cells[y][x].fg
+    }
+
+    #[test]
+    fn text_and_cursor_moves()
+    {
+        let mut p = panel(10, 4);
+        parser().write_str(&mut p, "ab\x1b[3;5Hc\x1b[Ad\x1b[2De\r\nf");
+        assert_eq!(text(&p), "ab\n    ed\nf   c\n");
+    }
+
+    #[test]
+    fn sequences_split_between_writes()
+    {
+        let mut p = panel(10, 2);
+        let mut ansi = parser();
+        let chunks: [&[u8]; 7] = [b"\x1b", b"[", b"3", b"2;", b"4", b"m", b"X\xc3"];
+        for chunk in chunks {
+            ansi.write(&mut p, chunk);
+        }
+        ansi.write(&mut p, b"\xa9\x1b]0;title\x07\x1b[0mY");
+
+        assert_eq!(text(&p), "X\u{e9}Y\n");
+        assert_eq!(fg(&p, 0, 0), ANSI_COLORS[2].into());
+        assert!(p.cells[0][1].attrs.contains(Attrs::UNDERLINE));
+        assert_eq!(fg(&p, 2, 0), WHITE.into());
+        assert_eq!(p.cells[0][2].attrs, Attrs::NONE);
+    }
+
+    #[test]
+    fn bold_brightens_the_current_color()
+    {
+        let mut p = panel(10, 1);
+        parser().write_str(&mut p, "\x1b[31ma\x1b[1mb\x1b[22mc\x1b[1;34md\x1b[39me\x1b[0mf");
+        let colors: Vec<CellColor> = (0..6).map(|x| fg(&p, x, 0)).collect();
+        assert_eq!(colors, [ANSI_COLORS[1], ANSI_COLORS[9], ANSI_COLORS[1],
+                            ANSI_COLORS[12], WHITE, WHITE].map(CellColor::from));
+    }
+
+    #[test]
+    fn private_modes()
+    {
+        let mut p = panel(4, 2);
+        let mut ansi = parser();
+        p.show_cursor(true);
+        ansi.write_str(&mut p, "\x1b[?25l");
+        assert!(!p.cursor_style.visible);
+        ansi.write_str(&mut p, "\x1b[?25h\x1b[?7l");
+        assert!(p.cursor_style.visible);
+        assert!(!p.wrap);
+    }
+
+    #[test]
+    fn out_of_range_parameters_are_clamped()
+    {
+        let mut p = panel(10, 4);
+        let mut ansi = parser();
+        ansi.write_str(&mut p, "\x1b[2;99r");
+        assert_eq!((p.scroll_top, p.scroll_bottom), (1, 3));
+
+        ansi.write_str(&mut p, "\x1b[4294967295B\x1b[4294967295C\x1b[99;99Hx\x1b[4294967295X");
+        // the cursor waits past the last column to wrap
+        assert_eq!((p.cursor_x, p.cursor_y), (10, 3));
+        assert_eq!(p.cells[3][9].ch, ' ');
+
+        let mut empty = panel(0, 0);
+        ansi.write_str(&mut empty, "\x1b[r\x1b[5;5Hx\x1b[2J");
+        assert_eq!((empty.scroll_top, empty.scroll_bottom), (0, 0));
+    }
+}
@@ -0,0 +1,389 @@
+//! Keeping a panel's pixels in a render target between frames, so each
+//! frame only redraws the cells that changed and then copies the target
+//! to the screen.
+//!
+//! Changes are found by comparing the cells with a copy of what the target
+//! shows, so writes straight to `Panel::cells` are picked up too. When the
+//! palette changes, only cells using the entries that changed are redrawn.
+//! The palette with its cycles applied is worked out once and kept until
+//! the panel's palette or one of its cycles moves on, rather than on every
+//! call.
+//!
+//! Only panels with an opaque erase color use a render target. Drawing
+//! translucent colors into a target and then the target onto the screen
+//! applies their alpha twice with macroquad's blending, so panels with a
+//! transparent or translucent erase color keep their meshes instead and
+//! draw them straight to the screen each frame, rebuilding them in full
+//! whenever anything changes.
+//!
+//! The cursor is drawn over the panel afterwards, so it never ends up in
+//! the target or the meshes.
+
+use macroquad::prelude::*;
+
+use crate::cursor::{cursor_cell, CursorStyle};
+use crate::font::BdgFont;
+use crate::palette::{CellColor, Palette, PaletteCycle};
+use crate::mesh::PanelMesh;
+use crate::panel::{Attrs, Cell, Panel};
+
+#[derive(Default)]
+pub(crate) struct RenderCache {
+    target: Option<RenderTarget>,
+
+    /// The panel drawn at `position`, for panels without an opaque erase
+    /// color.
+    mesh: Option<PanelMesh>,
+
+    /// What the target or mesh shows, and what it was drawn with. `font`
+    /// is `None` when it must be redrawn in full.
+    cells: Vec<Vec<Cell>>,
+    font: Option<BdgFont>,
+    palette: Palette,
+    pixel_scale: u32,
+    erase_color: Option<Color>,
+    flash_on: bool,
+
+    /// Rectangles of cells, `(x, y, w, h)`, to redraw even if unchanged.
+    invalid: Vec<(u32, u32, u32, u32)>,
+
+    /// Where the target was last copied to the screen.
+    position: Option<(f32, f32)>,
+
+    /// Where the cursor was at the last draw and the time it got there,
+    /// which its blink is timed from.
+    cursor_at: Option<(u32, u32)>,
+    cursor_since: f64,
+
+    /// The cursor drawn over the target last time, if it was showing.
+    cursor_drawn: Option<((u32, u32), CursorStyle)>,
+
+    resolved: Option<ResolvedPalette>,
+}
+
+/// The panel's palette with its cycles applied.
+struct ResolvedPalette {
+    /// What it was worked out from: the panel's palette, and each cycle
+    /// with how far it had stepped.
+    source: Palette,
+    cycles: Vec<(PaletteCycle, i64)>,
+
+    palette: Palette,
+    /// Which entries differ from the palette the target was drawn with.
+    recolored: Vec<bool>,
+}
+
+impl RenderCache {
+    /// Forgets what the target shows, so the next draw redraws it all.
+    pub(crate) fn invalidate(&mut self)
+    {
+        self.font = None;
+    }
+
+    pub(crate) fn invalidate_rect(&mut self, x: u32, y: u32, w: u32, h: u32)
+    {
+        self.invalid.push((x, y, w, h));
+    }
+
+    /// Brings `resolved` up to date with the panel's palette and cycles at
+    /// time `now`.
+    pub(crate) fn resolve_palette(&mut self, panel: &Panel, now: f64)
+    {
+        let cycles = panel.palette_cycles.iter().map(|&cycle| (cycle, cycle.steps(now)));
+        if self.resolved.as_ref().is_some_and(|resolved| {
+            resolved.source == panel.palette && resolved.cycles.iter().copied().eq(cycles.clone())
+        }) {
+            return;
+        }
+
+        let palette = panel.palette_at(now);
+        let recolored = (0..=255).map(|i| palette.color(i) != self.palette.color(i)).collect();
+        self.resolved = Some(ResolvedPalette {
+            source: panel.palette.clone(),
+            cycles: cycles.collect(),
+            palette,
+            recolored,
+        });
+    }
+
+    /// The cells that look different from what was drawn in flash phase
+    /// `flash_on` with the last palette from `resolve_palette`, or `None`
+    /// if the whole panel needs drawing.
+    pub(crate) fn dirty_cells(&self, panel: &Panel, flash_on: bool) -> Option<Vec<(u32, u32)>>
+    {
+        let font = self.font.as_ref()?;
+        let resolved = self.resolved.as_ref()?;
+        if !font.ptr_eq(&panel.font)
+            || self.pixel_scale != panel.pixel_scale
+            || self.erase_color != panel.erase_color
+            || self.cells.len() != panel.cells.len()
+            || self.cells.first().map(Vec::len) != panel.cells.first().map(Vec::len)
+        {
+            return None;
+        }
+
+        let flash_flipped = flash_on != self.flash_on;
+        let uses_recolored = |color: CellColor| match color {
+            CellColor::Index(i) => resolved.recolored[i as usize],
+            CellColor::Rgb(_) => false,
+        };
+
+        let mut dirty = vec![];
+        for (y, (row, drawn)) in panel.cells.iter().zip(&self.cells).enumerate() {
+            for (x, (cell, old)) in row.iter().zip(drawn).enumerate() {
+                let (x, y) = (x as u32, y as u32);
+                if cell != old
+                    || (flash_flipped && cell.attrs.contains(Attrs::FLASH))
+                    || uses_recolored(cell.fg)
+                    || cell.bg.is_some_and(uses_recolored)
+                    || self.invalid.iter().any(|&(rx, ry, rw, rh)| {
+                        x >= rx && x - rx < rw && y >= ry && y - ry < rh
+                    })
+                {
+                    dirty.push((x, y));
+                }
+            }
+        }
+        Some(dirty)
+    }
+
+    /// Whether the cursor is showing at time `now`. A cursor that moved
+    /// since the last draw starts its blink again in the on phase.
+    pub(crate) fn cursor_on(&self, panel: &Panel, now: f64) -> bool
+    {
+        if !panel.cursor_style.visible || cursor_cell(panel).is_none() {
+            return false;
+        }
+        match panel.cursor_style.blink {
+            Some(half) if half > 0.0 && self.cursor_at == Some((panel.cursor_x, panel.cursor_y)) => {
+                (((now - self.cursor_since) / half) as u64).is_multiple_of(2)
+            }
+            _ => true,
+        }
+    }
+
+    fn shown_cursor(&self, panel: &Panel, now: f64) -> Option<((u32, u32), CursorStyle)>
+    {
+        self.cursor_on(panel, now).then_some(((panel.cursor_x, panel.cursor_y), panel.cursor_style))
+    }
+
+    /// Whether drawing now would change what's on screen.
+    pub(crate) fn changed(&mut self, panel: &Panel, flash_on: bool, now: f64) -> bool
+    {
+        self.resolve_palette(panel, now);
+        self.position != Some((panel.screen_x, panel.screen_y))
+            || self.cursor_drawn != self.shown_cursor(panel, now)
+            || self.dirty_cells(panel, flash_on).is_none_or(|dirty| !dirty.is_empty())
+    }
+
+    /// Brings the target or meshes up to date with `panel` and draws them
+    /// at the panel's position, with the cursor on top.
+    pub(crate) fn draw(&mut self, panel: &Panel, flash_on: bool, now: f64)
+    {
+        let cursor_at = (panel.cursor_x, panel.cursor_y);
+        if self.cursor_at != Some(cursor_at) {
+            self.cursor_at = Some(cursor_at);
+            self.cursor_since = now;
+        }
+
+        if panel.screen_width < 1.0 || panel.screen_height < 1.0 {
+            return;
+        }
+
+        self.resolve_palette(panel, now);
+        let Some(palette) = self.resolved.as_ref().map(|resolved| resolved.palette.clone()) else {
+            return;
+        };
+        let position = vec2(panel.screen_x, panel.screen_y);
+        let dirty = self.dirty_cells(panel, flash_on);
+        let redrawn = if panel.erase_color.is_some_and(|c| c.a >= 1.0) {
+            self.mesh = None;
+            match dirty {
+                Some(dirty) if dirty.is_empty() => false,
+                only => {
+                    self.redraw_target(panel, flash_on, &palette, only.as_deref());
+                    true
+                }
+            }
+        } else if dirty.is_none_or(|dirty| !dirty.is_empty())
+            || self.position != Some((position.x, position.y))
+        {
+            self.target = None;
+            self.mesh = Some(PanelMesh::build_cells(panel, flash_on, &palette, position, None));
+            true
+        } else {
+            false
+        };
+
+        if redrawn {
+            self.drawn(panel, flash_on);
+        }
+
+        if let Some(target) = &self.target {
+            draw_texture_ex(&target.texture, panel.screen_x, panel.screen_y, WHITE,
+                            DrawTextureParams {
+                                dest_size: Some(target.texture.size()),
+                                flip_y: true,
+                                ..Default::default()
+                            });
+        }
+        if let Some(mesh) = &self.mesh {
+            mesh.draw();
+        }
+        self.position = Some((panel.screen_x, panel.screen_y));
+
+        self.cursor_drawn = self.shown_cursor(panel, now);
+        if self.cursor_drawn.is_some() {
+            PanelMesh::build_cursor(panel, flash_on, &palette, position).draw();
+        }
+    }
+
+    /// Records that the target or meshes now show `panel` as it looks in
+    /// flash phase `flash_on` with the resolved palette.
+    fn drawn(&mut self, panel: &Panel, flash_on: bool)
+    {
+        self.cells = panel.cells.clone();
+        self.font = Some(panel.font.clone());
+        self.pixel_scale = panel.pixel_scale;
+        self.erase_color = panel.erase_color;
+        self.flash_on = flash_on;
+        self.invalid.clear();
+        if let Some(resolved) = &mut self.resolved {
+            resolved.recolored.fill(false);
+            self.palette = resolved.palette.clone();
+        }
+    }
+
+    /// Draws the cells in `only`, or the whole panel, into the target.
+    fn redraw_target(&mut self, panel: &Panel, flash_on: bool, palette: &Palette,
+                     only: Option<&[(u32, u32)]>)
+    {
+        let size = vec2(panel.screen_width.floor(), panel.screen_height.floor());
+        if !self.target.as_ref().is_some_and(|target| target.texture.size() == size) {
+            let target = render_target(size.x as u32, size.y as u32);
+            target.texture.set_filter(FilterMode::Nearest);
+            self.target = Some(target);
+        }
+        let Some(target) = self.target.clone() else {
+            return;
+        };
+
+        push_camera_state();
+        let mut camera = Camera2D::from_display_rect(Rect::new(0.0, 0.0, size.x, size.y));
+        camera.render_target = Some(target);
+        set_camera(&camera);
+        if only.is_none() {
+            clear_background(BLANK);
+        }
+        PanelMesh::build_cells(panel, flash_on, palette, Vec2::ZERO, only).draw();
+        pop_camera_state();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::font::builtin;
+    use crate::panel::FLASH_HALF_PERIOD;
+
+    fn panel() -> Panel
+    {
+        let mut panel = Panel::builder(builtin::apple2_40col(), 4, 2).build();
+        panel.set_palette_colors(1, None);
+        panel.write_string("ab");
+        panel.set_palette_colors(5, Some(2));
+        panel.write_string("c");
+        panel
+    }
+
+    /// A cache that has just drawn `panel` in flash phase `flash_on` at
+    /// time `now`.
+    fn drawn_cache(panel: &Panel, flash_on: bool, now: f64) -> RenderCache
+    {
+        let mut cache = RenderCache::default();
+        cache.resolve_palette(panel, now);
+        cache.drawn(panel, flash_on);
+        cache.position = Some((panel.screen_x, panel.screen_y));
+        cache.cursor_at = Some((panel.cursor_x, panel.cursor_y));
+        cache.cursor_since = now;
+        cache.cursor_drawn = cache.shown_cursor(panel, now);
+        cache
+    }
+
+    #[test]
+    fn nothing_is_dirty_after_drawing()
+    {
+        let mut panel = panel();
+        let mut cache = drawn_cache(&panel, false, 0.0);
+        assert_eq!(RenderCache::default().dirty_cells(&panel, false), None);
+        assert_eq!(cache.dirty_cells(&panel, false), Some(vec![]));
+        assert!(!cache.changed(&panel, false, 0.0));
+
+        panel.put_char('x', 3, 1);
+        assert_eq!(cache.dirty_cells(&panel, false), Some(vec![(3, 1)]));
+        assert!(cache.changed(&panel, false, 0.0));
+    }
+
+    #[test]
+    fn flashing_and_invalidated_cells_are_dirty()
+    {
+        let mut panel = panel();
+        panel.write_string_attrs("d", Attrs::FLASH);
+        let mut cache = drawn_cache(&panel, false, 0.0);
+        assert_eq!(cache.dirty_cells(&panel, true), Some(vec![(3, 0)]));
+
+        cache.invalidate_rect(0, 1, 2, 5);
+        assert_eq!(cache.dirty_cells(&panel, false), Some(vec![(0, 1), (1, 1)]));
+        cache.invalidate();
+        assert_eq!(cache.dirty_cells(&panel, false), None);
+
+        let mut cache = drawn_cache(&panel, false, 0.0);
+        panel.screen_x += 1.0;
+        assert_eq!(cache.dirty_cells(&panel, false), Some(vec![]));
+        assert!(cache.changed(&panel, false, 0.0));
+        panel.erase_color = Some(BLUE);
+        assert_eq!(cache.dirty_cells(&panel, false), None);
+        cache.drawn(&panel, false);
+        assert_eq!(cache.dirty_cells(&panel, false), Some(vec![]));
+    }
+
+    #[test]
+    fn palette_is_resolved_once_per_step()
+    {
+        let mut panel = panel();
+        panel.add_palette_cycle(PaletteCycle::new(1, 2, 0.5));
+        let mut cache = drawn_cache(&panel, false, 0.0);
+        let resolved = cache.resolved.as_ref().unwrap().palette.colors.as_ptr();
+
+        cache.resolve_palette(&panel, 0.4);
+        assert_eq!(cache.resolved.as_ref().unwrap().palette.colors.as_ptr(), resolved);
+        assert_eq!(cache.dirty_cells(&panel, false), Some(vec![]));
+
+        // entries 1 and 2 swap, recoloring "ab" and the background of "c"
+        cache.resolve_palette(&panel, 0.6);
+        assert_eq!(cache.dirty_cells(&panel, false), Some(vec![(0, 0), (1, 0), (2, 0)]));
+        cache.drawn(&panel, false);
+        assert_eq!(cache.dirty_cells(&panel, false), Some(vec![]));
+
+        // a new palette is picked up even if the cycles haven't moved
+        panel.palette.colors[5] = RED;
+        cache.resolve_palette(&panel, 0.7);
+        assert_eq!(cache.dirty_cells(&panel, false), Some(vec![(2, 0)]));
+    }
+
+    #[test]
+    fn idle_panel_without_a_cursor_is_unchanged()
+    {
+        let mut panel = panel();
+        let mut cache = drawn_cache(&panel, false, 0.0);
+        for now in [0.1, 0.3, 0.6, 1.0, 10.0] {
+            assert!(!cache.changed(&panel, false, now), "changed at {}", now);
+        }
+
+        // a blinking cursor changes the screen every half period
+        panel.show_cursor(true);
+        let mut cache = drawn_cache(&panel, false, 0.0);
+        assert!(!cache.changed(&panel, false, 0.1));
+        assert!(cache.changed(&panel, false, FLASH_HALF_PERIOD + 0.01));
+    }
+}
@@ -0,0 +1,118 @@
+//! The text cursor drawn over a panel at `cursor_x`, `cursor_y`.
+//!
+//! The cursor isn't part of the cells: it's drawn on top each frame, so
+//! blinking it never redraws the panel underneath. Its blink restarts in
+//! the on phase whenever it moves, so it stays visible while typing.
+
+use macroquad::prelude::*;
+
+use crate::palette::Palette;
+use crate::panel::{resolve_cell_colors, Panel, FLASH_HALF_PERIOD};
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Default)]
+pub enum CursorShape {
+    /// The whole cell, with the character under it drawn inverse.
+    #[default]
+    Block,
+    /// The bottom pixel row of the cell.
+    Underline,
+    /// The left pixel column of the cell.
+    Bar,
+    /// A one-pixel checkerboard replacing the character, like the Apple
+    /// IIe's 80-column cursor.
+    Checkerboard,
+}
+
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub struct CursorStyle {
+    pub shape: CursorShape,
+    pub visible: bool,
+
+    /// Seconds spent in each half of the blink, or `None` for a steady
+    /// cursor.
+    pub blink: Option<f64>,
+
+    /// Drawn in this color instead of the foreground of the cell under
+    /// it.
+    pub color: Option<Color>,
+}
+
+impl Default for CursorStyle {
+    /// A block blinking at the flash rate, as on the Apple II, but hidden:
+    /// a blinking cursor redraws the panel every blink, so panels that take
+    /// typing turn it on with `Panel::show_cursor`.
+    fn default() -> CursorStyle
+    {
+        CursorStyle {
+            shape: CursorShape::Block,
+            visible: false,
+            blink: Some(FLASH_HALF_PERIOD),
+            color: None,
+        }
+    }
+}
+
+/// What to draw for the cursor, in screen pixels relative to the top left
+/// corner of its cell: solid rectangles in order, then optionally a glyph
+/// covering the cell.
+pub(crate) struct CursorPaint {
+    pub cell: (u32, u32),
+    pub rects: Vec<(Rect, Color)>,
+    pub glyph: Option<(char, Color)>,
+}
+
+/// The cell the cursor is drawn in. A cursor waiting to wrap past the
+/// right edge is shown in the last column.
+pub(crate) fn cursor_cell(panel: &Panel) -> Option<(u32, u32)>
+{
+    if panel.char_width == 0 || panel.cursor_y >= panel.char_height {
+        return None;
+    }
+    Some((panel.cursor_x.min(panel.char_width - 1), panel.cursor_y))
+}
+
+/// The cursor as it looks in its on phase, or `None` if it's hidden or
+/// off the panel.
+pub(crate) fn cursor_paint(panel: &Panel, palette: &Palette, flash_on: bool) -> Option<CursorPaint>
+{
+    let style = &panel.cursor_style;
+    if !style.visible {
+        return None;
+    }
+    let (x, y) = cursor_cell(panel)?;
+    let cell = panel.cells.get(y as usize)?.get(x as usize)?;
+
+    let (fg, bg) = resolve_cell_colors(cell, palette, panel.erase_color, flash_on);
+    let color = style.color.unwrap_or(fg);
+    let back = bg.or(panel.erase_color).unwrap_or(BLACK);
+
+    let scale = panel.pixel_scale as f32;
+    let cell_w = panel.font.width as f32 * scale;
+    let cell_h = panel.font.height as f32 * scale;
+    let full = Rect::new(0.0, 0.0, cell_w, cell_h);
+
+    let mut paint = CursorPaint { cell: (x, y), rects: vec![], glyph: None };
+    match style.shape {
+        CursorShape::Block => {
+            paint.rects.push((full, color));
+            if cell.ch != ' ' {
+                paint.glyph = Some((cell.ch, back));
+            }
+        }
+        CursorShape::Underline => {
+            paint.rects.push((Rect::new(0.0, cell_h - scale, cell_w, scale), color));
+        }
+        CursorShape::Bar => {
+            paint.rects.push((Rect::new(0.0, 0.0, scale, cell_h), color));
+        }
+        CursorShape::Checkerboard => {
+            paint.rects.push((full, back));
+            for gy in 0..panel.font.height {
+                for gx in (gy % 2..panel.font.width).step_by(2) {
+                    paint.rects.push((Rect::new(gx as f32 * scale, gy as f32 * scale, scale, scale), color));
+                }
+            }
+        }
+    }
+    Some(paint)
+}
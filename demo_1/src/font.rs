@@ -1,24 +1,494 @@
+use std::cell::OnceCell;
+use std::collections::HashMap;
+use std::fmt;
+use std::ops::Deref;
+use std::rc::Rc;
+
 use macroquad::prelude::*;
 
-#[derive(Debug, Copy, Clone)]
-pub struct BdgFont<'a> {
-    name: &'a str,
+pub mod bdf;
+pub mod bitmap;
+pub mod builtin;
+pub mod manifest;
+pub mod psf;
+pub mod registry;
+pub mod ttf;
+
+pub use manifest::load_font;
+pub use registry::FontRegistry;
+
+/// Where the glyph cells live inside a font atlas texture.
+///
+/// Cells are laid out left to right, top to bottom, `columns` cells per
+/// row, with `first_char` in cell number `first_cell` (usually the top left
+/// cell). `padding_x` and `padding_y` are the pixels before the first cell,
+/// and `spacing_x` and `spacing_y` are the pixels between neighbouring
+/// cells.
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub struct AtlasLayout {
+    pub columns: u32,
+    pub first_char: u32,
+    pub last_char: u32,
+    pub first_cell: u32,
+
+    pub padding_x: u32,
+    pub padding_y: u32,
+    pub spacing_x: u32,
+    pub spacing_y: u32,
+}
+
+impl AtlasLayout {
+    /// A tightly packed atlas with no padding or spacing, matching what
+    /// `Tools/makefont.py` writes out.
+    pub fn new(columns: u32, first_char: u32, last_char: u32) -> Result<AtlasLayout, FontError>
+    {
+        let layout = AtlasLayout {
+            columns,
+            first_char,
+            last_char,
+            first_cell: 0,
+            padding_x: 0,
+            padding_y: 0,
+            spacing_x: 0,
+            spacing_y: 0,
+        };
+        layout.validate()?;
+        Ok(layout)
+    }
+
+    /// Checks the layout can be used to find cells.
+    pub fn validate(&self) -> Result<(), FontError>
+    {
+        if self.columns == 0 {
+            return Err(FontError::Layout("an atlas needs at least one column".to_string()));
+        }
+        if self.first_char > self.last_char {
+            return Err(FontError::Layout(format!(
+                "first character U+{:04X} is after last character U+{:04X}",
+                self.first_char, self.last_char)));
+        }
+        Ok(())
+    }
+}
+
+impl Default for AtlasLayout {
+    /// 16 columns of printable ASCII, U+0020 to U+007F.
+    fn default() -> AtlasLayout
+    {
+        AtlasLayout::new(16, 0x20, 0x7f).unwrap()
+    }
+}
+
+/// A font atlas image, shared by every page cut from it.
+///
+/// The pixels are kept in memory so fonts can be rendered without a GPU,
+/// and the texture is only uploaded the first time a glyph is drawn.
+/// Atlases made from an existing texture read its pixels back the first
+/// time they're needed instead.
+#[derive(Debug, Clone)]
+pub struct FontAtlas {
+    data: Rc<AtlasData>,
+}
+
+#[derive(Debug)]
+struct AtlasData {
+    image: OnceCell<Image>,
+    texture: OnceCell<Texture2D>,
+}
+
+impl FontAtlas {
+    pub fn new(image: Image) -> FontAtlas
+    {
+        FontAtlas {
+            data: Rc::new(AtlasData { image: OnceCell::from(image), texture: OnceCell::new() }),
+        }
+    }
+
+    pub fn from_texture(texture: &Texture2D) -> FontAtlas
+    {
+        FontAtlas {
+            data: Rc::new(AtlasData { image: OnceCell::new(), texture: OnceCell::from(texture.clone()) }),
+        }
+    }
+
+    /// The atlas pixels, white glyphs on a transparent background.
+    pub fn image(&self) -> &Image
+    {
+        self.data.image.get_or_init(|| {
+            self.data.texture.get().expect("font atlas has no pixels").get_texture_data()
+        })
+    }
+
+    /// The atlas texture, uploaded with nearest-neighbour filtering on
+    /// first use.
+    pub fn texture(&self) -> &Texture2D
+    {
+        self.data.texture.get_or_init(|| {
+            let texture = Texture2D::from_image(self.image());
+            texture.set_filter(FilterMode::Nearest);
+            texture
+        })
+    }
+}
+
+/// One atlas texture (or region of one) and the codepoints it covers.
+///
+/// Codepoints normally map onto cells in order, as described by `layout`.
+/// Fonts whose glyphs aren't in codepoint order, such as PSF fonts with a
+/// Unicode table, set `cell_map` instead, which maps each character
+/// straight to its cell number.
+#[derive(Debug, Clone)]
+pub struct FontPage {
+    pub atlas: FontAtlas,
+    pub layout: AtlasLayout,
+    pub cell_map: Option<HashMap<char, u32>>,
+}
+
+impl FontPage {
+    pub fn contains(&self, c: char) -> bool
+    {
+        self.cell_index(c).is_some()
+    }
+
+    fn cell_index(&self, c: char) -> Option<u32>
+    {
+        if let Some(map) = &self.cell_map {
+            return map.get(&c).copied();
+        }
+
+        let codepoint = c as u32;
+        if codepoint < self.layout.first_char || codepoint > self.layout.last_char {
+            return None;
+        }
+        Some(codepoint - self.layout.first_char + self.layout.first_cell)
+    }
+
+    /// The source rectangle of `c` in this page's atlas for cells of
+    /// `width` by `height` pixels, or `None` if the page doesn't cover it.
+    pub fn glyph_rect(&self, c: char, width: u32, height: u32) -> Option<Rect>
+    {
+        let index = self.cell_index(c)?;
+        if self.layout.columns == 0 {
+            return None;
+        }
+
+        let cx = index % self.layout.columns;
+        let cy = index / self.layout.columns;
+
+        let tx = self.layout.padding_x + cx * (width + self.layout.spacing_x);
+        let ty = self.layout.padding_y + cy * (height + self.layout.spacing_y);
+
+        Some(Rect {
+            x: tx as f32,
+            y: ty as f32,
+            w: width as f32,
+            h: height as f32,
+        })
+    }
+}
+
+/// The contents of a font, shared by every `BdgFont` handle to it.
+#[derive(Debug, Clone)]
+pub struct FontData {
+    name: String,
     pub width: u32,
     pub height: u32,
 
-    pub texture: &'a Texture2D,
+    /// Pixels from the top of a cell down to the text baseline.
+    pub baseline: u32,
+
+    pub pages: Vec<FontPage>,
+
+    /// Drawn in place of characters the atlas has no glyph for.
+    pub replacement: Option<char>,
+}
+
+impl FontData {
+    pub fn name(&self) -> &str
+    {
+        &self.name
+    }
+
+    /// The page holding `c` and the source rectangle of `c` in it, or
+    /// `None` if no page has a glyph for it.
+    pub fn find_glyph(&self, c: char) -> Option<(&FontPage, Rect)>
+    {
+        self.pages.iter()
+            .find_map(|page| page.glyph_rect(c, self.width, self.height)
+                      .map(|rect| (page, rect)))
+    }
+
+    /// Like `find_glyph`, but falls back to the replacement glyph for
+    /// unmapped characters.
+    pub fn find_glyph_or_replacement(&self, c: char) -> Option<(&FontPage, Rect)>
+    {
+        self.find_glyph(c)
+            .or_else(|| self.replacement.and_then(|r| self.find_glyph(r)))
+    }
+
+    /// The source rectangle of `c` in its page's atlas, or `None` if the
+    /// font has no glyph for it.
+    pub fn glyph_rect(&self, c: char) -> Option<Rect>
+    {
+        self.find_glyph(c).map(|(_, rect)| rect)
+    }
+
+    /// The pixel row underlines go in: the baseline, kept inside the cell.
+    pub fn underline_row(&self) -> u32
+    {
+        self.baseline.min(self.height.saturating_sub(1))
+    }
+}
+
+/// A fixed-cell bitmap font made of one or more pages. Every page uses the
+/// same cell size; when pages overlap, the one added first wins.
+///
+/// `BdgFont` is a reference-counted handle, so cloning one is cheap and
+/// the clones share textures. Changing a font through a handle copies the
+/// data first if other handles still point at it.
+#[derive(Debug, Clone)]
+pub struct BdgFont {
+    data: Rc<FontData>,
+}
+
+impl Deref for BdgFont {
+    type Target = FontData;
+
+    fn deref(&self) -> &FontData
+    {
+        &self.data
+    }
+}
+
+impl From<FontData> for BdgFont {
+    fn from(data: FontData) -> BdgFont
+    {
+        BdgFont { data: Rc::new(data) }
+    }
+}
+
+impl BdgFont {
+    fn data_mut(&mut self) -> &mut FontData
+    {
+        Rc::make_mut(&mut self.data)
+    }
+
+    /// True if both handles share the same font data.
+    pub fn ptr_eq(&self, other: &BdgFont) -> bool
+    {
+        Rc::ptr_eq(&self.data, &other.data)
+    }
+
+    pub fn set_name(&mut self, name: &str)
+    {
+        self.data_mut().name = name.to_string();
+    }
+
+    pub fn set_replacement(&mut self, replacement: Option<char>)
+    {
+        self.data_mut().replacement = replacement;
+    }
+
+    /// Adds a page covering the codepoints in `layout`.
+    pub fn add_page(&mut self, atlas: &FontAtlas, layout: AtlasLayout)
+    {
+        self.data_mut().pages.push(FontPage { atlas: atlas.clone(), layout, cell_map: None });
+    }
+
+    /// Appends all of `other`'s pages, so its glyphs fill in whatever this
+    /// font doesn't already have. Fails if the cell sizes differ.
+    pub fn add_pages_from(&mut self, other: &BdgFont) -> Result<(), FontError>
+    {
+        if self.width != other.width || self.height != other.height {
+            return Err(FontError::Format {
+                path: other.name.clone(),
+                message: format!("can't merge {}x{} font into {}x{} font",
+                                 other.width, other.height, self.width, self.height),
+            });
+        }
+        self.data_mut().pages.extend(other.pages.iter().cloned());
+        Ok(())
+    }
 }
 
 pub fn make_font(texture: &Texture2D, width: u32, height: u32) -> BdgFont
 {
-    println!("I made a font");
+    font_from_texture(texture, width, height, AtlasLayout::default())
+}
+
+pub fn make_font_with_layout(texture: &Texture2D, width: u32, height: u32,
+                             layout: AtlasLayout) -> Result<BdgFont, FontError>
+{
+    layout.validate()?;
+    Ok(font_from_texture(texture, width, height, layout))
+}
+
+fn font_from_texture(texture: &Texture2D, width: u32, height: u32, layout: AtlasLayout) -> BdgFont
+{
+    BdgFont::from(FontData {
+        name: String::new(),
+        width,
+        height,
+        baseline: height,
+        pages: vec![FontPage { atlas: FontAtlas::from_texture(texture), layout, cell_map: None }],
+        replacement: Some('?'),
+    })
+}
+
+/// Why a font couldn't be loaded.
+#[derive(Debug)]
+pub enum FontError {
+    /// A file couldn't be read.
+    File { path: String, source: macroquad::Error },
+    /// An atlas image couldn't be decoded.
+    Image { path: String, source: macroquad::Error },
+    /// A font description was malformed or inconsistent.
+    Format { path: String, message: String },
+    /// An atlas layout can't be used to find glyph cells.
+    Layout(String),
+}
+
+impl fmt::Display for FontError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result
+    {
+        match self {
+            FontError::File { path, source } =>
+                write!(f, "can't read font file {}: {}", path, source),
+            FontError::Image { path, source } =>
+                write!(f, "can't decode font atlas {}: {}", path, source),
+            FontError::Format { path, message } =>
+                write!(f, "bad font {}: {}", path, message),
+            FontError::Layout(message) =>
+                write!(f, "bad atlas layout: {}", message),
+        }
+    }
+}
+
+impl std::error::Error for FontError {}
+
+#[cfg(test)]
+pub(crate) mod tests {
+    use super::*;
+
+    /// A texture handle that's never drawn, so tests don't need a window.
+    pub(crate) fn texture() -> Texture2D
+    {
+        Texture2D::from_miniquad_texture(miniquad::TextureId::from_raw_id(miniquad::RawId::OpenGl(0)))
+    }
+
+    #[test]
+    fn bad_layouts_are_rejected()
+    {
+        assert!(matches!(AtlasLayout::new(0, 0x20, 0x7f), Err(FontError::Layout(_))));
+        assert!(matches!(AtlasLayout::new(16, 0x7f, 0x20), Err(FontError::Layout(_))));
+        assert!(AtlasLayout::new(1, 0x41, 0x41).is_ok());
+
+        let layout = AtlasLayout { columns: 0, ..AtlasLayout::default() };
+        assert!(make_font_with_layout(&texture(), 6, 8, layout).is_err());
+    }
+
+    #[test]
+    fn glyph_rect_steps_through_cells()
+    {
+        let texture = texture();
+        let layout = AtlasLayout { padding_x: 1, spacing_x: 2, spacing_y: 1, ..AtlasLayout::default() };
+        let font = make_font_with_layout(&texture, 6, 8, layout).unwrap();
+        // 'A' is cell 0x21: row 2, column 1
+        assert_eq!(font.glyph_rect('A'), Some(Rect::new(9.0, 18.0, 6.0, 8.0)));
+        assert_eq!(make_font(&texture, 6, 8).glyph_rect(' '), Some(Rect::new(0.0, 0.0, 6.0, 8.0)));
+    }
+
+    #[test]
+    fn unmapped_characters_use_the_replacement()
+    {
+        let replacement = |font: &BdgFont, c| font.find_glyph_or_replacement(c).map(|(_, rect)| rect);
+        let texture = texture();
+        let mut font = make_font(&texture, 6, 8);
+        let question = font.glyph_rect('?');
+        assert!(question.is_some());
+        assert_eq!(font.glyph_rect('\n'), None);
+        assert_eq!(font.glyph_rect('\u{80}'), None);
+        assert_eq!(replacement(&font, '\n'), question);
+        assert_eq!(replacement(&font, 'A'), font.glyph_rect('A'));
+
+        font.set_replacement(None);
+        assert_eq!(replacement(&font, '\n'), None);
+        font.set_replacement(Some('\u{2591}'));
+        assert_eq!(replacement(&font, '\n'), None);
+
+        font.data_mut().pages[0].layout.columns = 0;
+        assert_eq!(font.glyph_rect('A'), None);
+    }
+
+    #[test]
+    fn earlier_pages_win()
+    {
+        let texture = texture();
+        let mut font = make_font(&texture, 6, 8);
+        // capitals and the box drawing block, in 8 column atlases
+        let atlas = FontAtlas::new(Image::empty());
+        font.add_page(&atlas, AtlasLayout::new(8, 0x41, 0x5a).unwrap());
+        font.add_page(&atlas, AtlasLayout::new(8, 0x2500, 0x257f).unwrap());
+
+        let page_of = |c| font.find_glyph_or_replacement(c).map(|(page, rect)| (page.layout.first_char, rect));
+        assert_eq!(page_of('B'), Some((0x20, Rect::new(12.0, 16.0, 6.0, 8.0))));
+        assert_eq!(page_of('\u{2501}'), Some((0x2500, Rect::new(6.0, 0.0, 6.0, 8.0))));
+        assert_eq!(page_of('\u{2509}'), Some((0x2500, Rect::new(6.0, 8.0, 6.0, 8.0))));
+        assert_eq!(page_of('\u{2580}'), page_of('?'));
+        assert_eq!(font.find_glyph('\u{2580}').map(|(_, rect)| rect), None);
+    }
+
+    #[test]
+    fn cell_maps_override_the_layout()
+    {
+        let texture = texture();
+        let mut font = make_font(&texture, 6, 8);
+        let mut page = font.pages[0].clone();
+        page.cell_map = Some(HashMap::from([('A', 3), ('\u{e9}', 17)]));
+        font.data_mut().pages.insert(0, page);
+
+        assert_eq!(font.glyph_rect('A'), Some(Rect::new(18.0, 0.0, 6.0, 8.0)));
+        assert_eq!(font.glyph_rect('\u{e9}'), Some(Rect::new(6.0, 8.0, 6.0, 8.0)));
+        // characters missing from the map fall through to later pages
+        assert_eq!(font.glyph_rect('B'), Some(Rect::new(12.0, 16.0, 6.0, 8.0)));
+        assert!(!font.pages[0].contains('B'));
+    }
+
+    #[test]
+    fn merging_pages_needs_matching_cells()
+    {
+        let texture = texture();
+        let mut font = make_font(&texture, 6, 8);
+        let shared = font.clone();
+        let hi = make_font_with_layout(&texture, 6, 8, AtlasLayout::new(16, 0x2500, 0x259f).unwrap()).unwrap();
+
+        font.add_pages_from(&hi).unwrap();
+        assert_eq!(font.pages.len(), 2);
+        assert_eq!(font.glyph_rect('\u{2501}'), Some(Rect::new(6.0, 0.0, 6.0, 8.0)));
+        // the other handle still has its own copy
+        assert_eq!(shared.pages.len(), 1);
+        assert!(!font.ptr_eq(&shared));
 
-    let f = BdgFont {
-        name: "foo",
-        width: width,
-        height: height,
-        texture: texture,
-    };
+        assert!(matches!(font.add_pages_from(&make_font(&texture, 8, 8)),
+                         Err(FontError::Format { .. })));
+        assert_eq!(font.pages.len(), 2);
+    }
 
-    return f;
+    #[test]
+    fn underline_stays_in_the_cell()
+    {
+        let mut data = FontData {
+            name: String::new(),
+            width: 6,
+            height: 8,
+            baseline: 8,
+            pages: vec![],
+            replacement: None,
+        };
+        assert_eq!(data.underline_row(), 7);
+        data.baseline = 6;
+        assert_eq!(data.underline_row(), 6);
+        data.height = 0;
+        assert_eq!(data.underline_row(), 0);
+    }
 }
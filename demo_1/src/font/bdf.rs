@@ -0,0 +1,224 @@
+//! X11 fonts in Glyph Bitmap Distribution Format.
+//!
+//! Every glyph is placed in a cell the size of `FONTBOUNDINGBOX`, shifted
+//! by its own `BBX` so that all glyphs share one baseline. Glyphs with an
+//! `ENCODING` of -1 have no codepoint and are skipped.
+
+use macroquad::prelude::*;
+
+use crate::font::bitmap::{font_from_glyphs, GlyphSet};
+use crate::font::{BdgFont, FontError};
+
+/// Parses a BDF font. `path` is only used in errors.
+pub fn parse_bdf(text: &str, path: &str) -> Result<GlyphSet, FontError>
+{
+    let bad = |line: usize, message: &str| FontError::Format {
+        path: path.to_string(),
+        message: format!("line {}: {}", line + 1, message),
+    };
+
+    let mut glyphs: Option<GlyphSet> = None;
+    let mut name = String::new();
+    // font bounding box: width, height, x offset, y offset
+    let mut bounds = (0, 0, 0, 0);
+
+    let mut encoding: Option<u32> = None;
+    let mut bbx = (0, 0, 0, 0);
+    let mut bitmap_row: Option<i32> = None;
+    let mut glyph = 0;
+
+    for (n, line) in text.lines().enumerate() {
+        let mut words = line.split_whitespace();
+        let Some(keyword) = words.next() else {
+            continue;
+        };
+        let args: Vec<&str> = words.collect();
+        let ints = || -> Result<Vec<i32>, FontError> {
+            args.iter()
+                .map(|a| a.parse::<i32>().map_err(|_| bad(n, "expected numbers")))
+                .collect()
+        };
+
+        if let Some(row) = bitmap_row {
+            if keyword == "ENDCHAR" {
+                bitmap_row = None;
+                continue;
+            }
+            let glyphs = glyphs.as_mut().ok_or_else(|| bad(n, "BITMAP before CHARS"))?;
+            let bits = u64::from_str_radix(keyword, 16).map_err(|_| bad(n, "bad bitmap row"))?;
+            let row_bits = keyword.len() as i32 * 4;
+            let (w, h, xo, yo) = bbx;
+            let top = (bounds.1 + bounds.3) - (h + yo);
+            for x in 0..w.min(row_bits) {
+                if bits & (1 << (row_bits - 1 - x)) != 0 {
+                    let px = xo - bounds.2 + x;
+                    let py = top + row;
+                    if px >= 0 && py >= 0 {
+                        glyphs.set_pixel(glyph, px as u32, py as u32, true);
+                    }
+                }
+            }
+            bitmap_row = Some(row + 1);
+            continue;
+        }
+
+        match keyword {
+            "FONT" if name.is_empty() => name = args.join(" "),
+            "FAMILY_NAME" => name = args.join(" ").trim_matches('"').to_string(),
+            "FONTBOUNDINGBOX" => {
+                let v = ints()?;
+                if v.len() != 4 || v[0] <= 0 || v[1] <= 0 {
+                    return Err(bad(n, "bad FONTBOUNDINGBOX"));
+                }
+                bounds = (v[0], v[1], v[2], v[3]);
+            }
+            "CHARS" => {
+                if bounds.0 == 0 {
+                    return Err(bad(n, "CHARS before FONTBOUNDINGBOX"));
+                }
+                let mut set = GlyphSet::new(&name, bounds.0 as u32, bounds.1 as u32)
+                    .ok_or_else(|| bad(n, "FONTBOUNDINGBOX is too big"))?;
+                set.baseline = (bounds.1 + bounds.3).clamp(0, bounds.1) as u32;
+                glyphs = Some(set);
+            }
+            "STARTCHAR" => {
+                encoding = None;
+                bbx = (bounds.0, bounds.1, bounds.2, bounds.3);
+            }
+            "ENCODING" => {
+                let v = ints()?;
+                encoding = v.first().and_then(|&e| u32::try_from(e).ok());
+            }
+            "BBX" => {
+                let v = ints()?;
+                if v.len() != 4 {
+                    return Err(bad(n, "bad BBX"));
+                }
+                bbx = (v[0], v[1], v[2], v[3]);
+            }
+            "BITMAP" => {
+                let glyphs = glyphs.as_mut().ok_or_else(|| bad(n, "BITMAP before CHARS"))?;
+                glyph = glyphs.add_glyph();
+                if let Some(c) = encoding.and_then(char::from_u32) {
+                    glyphs.char_map.entry(c).or_insert(glyph);
+                }
+                bitmap_row = Some(0);
+            }
+            _ => {}
+        }
+    }
+
+    let mut glyphs = glyphs.ok_or_else(|| FontError::Format {
+        path: path.to_string(),
+        message: "no CHARS section".to_string(),
+    })?;
+    if glyphs.name.is_empty() {
+        glyphs.name = name;
+    }
+    Ok(glyphs)
+}
+
+/// Loads a `.bdf` file and uploads it as a font.
+pub async fn load_bdf_font(path: &str) -> Result<BdgFont, FontError>
+{
+    let text = load_string(path).await
+        .map_err(|source| FontError::File { path: path.to_string(), source })?;
+    let glyphs = parse_bdf(&text, path)?;
+    font_from_glyphs(&glyphs)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const FONT: &str = "STARTFONT 2.1
+FONT -misc-tiny-medium-r-normal--4-40-75-75-c-30-iso10646-1
+FONTBOUNDINGBOX 3 4 0 -1
+STARTPROPERTIES 1
+FAMILY_NAME \"Tiny\"
+ENDPROPERTIES
+CHARS 3
+STARTCHAR A
+ENCODING 65
+BBX 3 3 0 0
+BITMAP
+40
+A0
+E0
+ENDCHAR
+STARTCHAR comma
+ENCODING 44
+BBX 1 2 1 -1
+BITMAP
+80
+80
+ENDCHAR
+STARTCHAR unnamed
+ENCODING -1
+BBX 3 4 0 -1
+BITMAP
+E0
+E0
+E0
+E0
+ENDCHAR
+ENDFONT
+";
+
+    fn error(text: &str) -> String
+    {
+        match parse_bdf(text, "test.bdf") {
+            Err(FontError::Format { message, .. }) => message,
+            other => panic!("expected a format error, got {:?}", other),
+        }
+    }
+
+    #[test]
+    fn glyphs_share_a_baseline()
+    {
+        let glyphs = parse_bdf(FONT, "test.bdf").unwrap();
+        assert_eq!(glyphs.name, "Tiny");
+        assert_eq!((glyphs.width, glyphs.height, glyphs.baseline), (3, 4, 3));
+
+        // the unencoded glyph is read but not mapped
+        assert_eq!(glyphs.glyphs.len(), 3);
+        assert_eq!(glyphs.char_map.len(), 2);
+
+        let a = &glyphs.glyphs[glyphs.char_map[&'A'] as usize];
+        assert_eq!(a, &[false, true, false,
+                        true, false, true,
+                        true, true, true,
+                        false, false, false]);
+        let comma = &glyphs.glyphs[glyphs.char_map[&','] as usize];
+        assert_eq!(comma, &[false, false, false,
+                            false, false, false,
+                            false, true, false,
+                            false, true, false]);
+    }
+
+    #[test]
+    fn baselines_stay_inside_the_cell()
+    {
+        let text = FONT.replace("FONTBOUNDINGBOX 3 4 0 -1", "FONTBOUNDINGBOX 3 4 0 2");
+        assert_eq!(parse_bdf(&text, "test.bdf").unwrap().baseline, 4);
+        let text = FONT.replace("FONTBOUNDINGBOX 3 4 0 -1", "FONTBOUNDINGBOX 3 4 0 -9");
+        assert_eq!(parse_bdf(&text, "test.bdf").unwrap().baseline, 0);
+    }
+
+    #[test]
+    fn malformed_fonts_are_rejected()
+    {
+        assert_eq!(error("CHARS 1\nFONTBOUNDINGBOX 3 4 0 -1\n"),
+                   "line 1: CHARS before FONTBOUNDINGBOX");
+        assert_eq!(error(&FONT.replace("BBX 1 2 1 -1", "BBX 1 2")), "line 18: bad BBX");
+        assert_eq!(error(&FONT.replace("BBX 1 2 1 -1", "BBX 1 two 1 -1")), "line 18: expected numbers");
+        assert_eq!(error("FONTBOUNDINGBOX 3 4 0 -1\n"), "no CHARS section");
+    }
+
+    #[test]
+    fn oversized_bounding_boxes_are_rejected()
+    {
+        let text = FONT.replace("FONTBOUNDINGBOX 3 4 0 -1", "FONTBOUNDINGBOX 100000 100000 0 0");
+        assert_eq!(error(&text), "line 7: FONTBOUNDINGBOX is too big");
+    }
+}
@@ -0,0 +1,159 @@
+//! Glyphs rasterized on the CPU, and packing them into an atlas font.
+//! The PSF, BDF and TrueType loaders all produce a `GlyphSet`.
+
+use std::collections::HashMap;
+
+use macroquad::prelude::*;
+
+use crate::font::{AtlasLayout, BdgFont, FontAtlas, FontData, FontError, FontPage};
+
+/// Columns in the atlases built by `font_from_glyphs`, matching the
+/// atlases made by `Tools/makefont.py`.
+pub const ATLAS_COLUMNS: u32 = 16;
+
+/// The widest and tallest glyph a `GlyphSet` holds. Font files give their
+/// own cell size, and this keeps a corrupt one from asking for gigabytes
+/// of glyph bitmaps.
+pub const MAX_GLYPH_SIZE: u32 = 256;
+
+/// A set of same-sized 1-bit glyphs plus the characters that use them.
+#[derive(Debug, Clone)]
+pub struct GlyphSet {
+    pub name: String,
+    pub width: u32,
+    pub height: u32,
+    pub baseline: u32,
+
+    /// One `width * height` row-major bitmap per glyph.
+    pub glyphs: Vec<Vec<bool>>,
+
+    /// Which glyph each character uses. Several characters may share one.
+    pub char_map: HashMap<char, u32>,
+}
+
+impl GlyphSet {
+    /// An empty set of `width` by `height` glyphs, or `None` if that's
+    /// no pixels or more than `MAX_GLYPH_SIZE` on a side.
+    pub fn new(name: &str, width: u32, height: u32) -> Option<GlyphSet>
+    {
+        if !(1..=MAX_GLYPH_SIZE).contains(&width) || !(1..=MAX_GLYPH_SIZE).contains(&height) {
+            return None;
+        }
+        Some(GlyphSet {
+            name: name.to_string(),
+            width,
+            height,
+            baseline: height,
+            glyphs: vec![],
+            char_map: HashMap::new(),
+        })
+    }
+
+    /// Adds a blank glyph and returns its index.
+    pub fn add_glyph(&mut self) -> u32
+    {
+        self.glyphs.push(vec![false; self.width as usize * self.height as usize]);
+        self.glyphs.len() as u32 - 1
+    }
+
+    pub fn set_pixel(&mut self, glyph: u32, x: u32, y: u32, on: bool)
+    {
+        if x < self.width && y < self.height {
+            self.glyphs[glyph as usize][(y * self.width + x) as usize] = on;
+        }
+    }
+}
+
+/// Packs the glyphs into an RGBA atlas, `ATLAS_COLUMNS` cells wide, with
+/// set pixels white and everything else transparent. Fails if the atlas
+/// would be more than 65535 pixels on a side.
+pub fn atlas_image(glyphs: &GlyphSet) -> Result<Image, FontError>
+{
+    let count = glyphs.glyphs.len().max(1) as u32;
+    let rows = count.div_ceil(ATLAS_COLUMNS);
+    let size = |cells: u32, pixels: u32| {
+        cells.checked_mul(pixels).and_then(|size| u16::try_from(size).ok())
+    };
+    let (Some(atlas_w), Some(atlas_h)) = (size(ATLAS_COLUMNS, glyphs.width), size(rows, glyphs.height)) else {
+        return Err(FontError::Format {
+            path: glyphs.name.clone(),
+            message: format!("{} glyphs of {}x{} don't fit in one atlas",
+                             glyphs.glyphs.len(), glyphs.width, glyphs.height),
+        });
+    };
+
+    let mut image = Image::gen_image_color(atlas_w, atlas_h, BLANK);
+    for (i, glyph) in glyphs.glyphs.iter().enumerate() {
+        let ox = (i as u32 % ATLAS_COLUMNS) * glyphs.width;
+        let oy = (i as u32 / ATLAS_COLUMNS) * glyphs.height;
+        for y in 0..glyphs.height {
+            for x in 0..glyphs.width {
+                if glyph[(y * glyphs.width + x) as usize] {
+                    image.set_pixel(ox + x, oy + y, WHITE);
+                }
+            }
+        }
+    }
+    Ok(image)
+}
+
+/// Packs the glyphs into a single-page font.
+pub fn font_from_glyphs(glyphs: &GlyphSet) -> Result<BdgFont, FontError>
+{
+    let first = glyphs.char_map.keys().map(|&c| c as u32).min().unwrap_or(0);
+    let last = glyphs.char_map.keys().map(|&c| c as u32).max().unwrap_or(0);
+
+    Ok(BdgFont::from(FontData {
+        name: glyphs.name.clone(),
+        width: glyphs.width,
+        height: glyphs.height,
+        baseline: glyphs.baseline,
+        pages: vec![FontPage {
+            atlas: FontAtlas::new(atlas_image(glyphs)?),
+            layout: AtlasLayout::new(ATLAS_COLUMNS, first, last)?,
+            cell_map: Some(glyphs.char_map.clone()),
+        }],
+        replacement: Some('?'),
+    }))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn glyphs_are_packed_in_rows()
+    {
+        let mut glyphs = GlyphSet::new("test", 2, 3).unwrap();
+        for i in 0..17 {
+            let glyph = glyphs.add_glyph();
+            glyphs.set_pixel(glyph, 1, 2, true);
+            glyphs.char_map.insert(char::from_u32(0x41 + i).unwrap(), glyph);
+        }
+        let image = atlas_image(&glyphs).unwrap();
+        assert_eq!((image.width, image.height), (32, 6));
+        assert_eq!(image.get_pixel(3, 2), WHITE);
+        assert_eq!(image.get_pixel(1, 5), WHITE);
+        assert_eq!(image.get_pixel(0, 0), BLANK);
+
+        let font = font_from_glyphs(&glyphs).unwrap();
+        assert_eq!((font.pages[0].layout.first_char, font.pages[0].layout.last_char), (0x41, 0x51));
+    }
+
+    #[test]
+    fn oversized_glyphs_are_rejected()
+    {
+        assert!(GlyphSet::new("empty", 0, 8).is_none());
+        assert!(GlyphSet::new("wide", MAX_GLYPH_SIZE + 1, 1).is_none());
+        assert!(GlyphSet::new("tall", 1, u32::MAX).is_none());
+        assert!(GlyphSet::new("big", MAX_GLYPH_SIZE, MAX_GLYPH_SIZE).is_some());
+
+        // 16 glyphs to a row, so 257 rows of 256 pixels
+        let mut glyphs = GlyphSet::new("tall", 1, MAX_GLYPH_SIZE).unwrap();
+        for _ in 0..ATLAS_COLUMNS * 257 {
+            glyphs.add_glyph();
+        }
+        assert!(matches!(atlas_image(&glyphs), Err(FontError::Format { .. })));
+        assert!(font_from_glyphs(&glyphs).is_err());
+    }
+}
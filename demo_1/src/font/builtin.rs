@@ -0,0 +1,64 @@
+//! Fonts compiled into the crate, so they work without an `assets`
+//! directory, e.g. in a WASM build or a binary run from elsewhere.
+//!
+//! Each call decodes the atlas again, so load a font once and keep it.
+
+use crate::font::manifest::{font_from_manifest_png, parse_manifest};
+use crate::font::BdgFont;
+
+const APPLE2_40COL_TOML: &str = include_str!("../../assets/40col.toml");
+const APPLE2_40COL_PNG: &[u8] = include_bytes!("../../assets/40col.png");
+const APPLE2_40COL_HI_TOML: &str = include_str!("../../assets/40col_hi.toml");
+const APPLE2_40COL_HI_PNG: &[u8] = include_bytes!("../../assets/40col_hi.png");
+const APPLE2_80COL_TOML: &str = include_str!("../../assets/80col.toml");
+const APPLE2_80COL_PNG: &[u8] = include_bytes!("../../assets/80col.png");
+const NES_TOML: &str = include_str!("../../assets/nes.toml");
+const NES_PNG: &[u8] = include_bytes!("../../assets/nes.png");
+
+fn embedded_font(name: &str, manifest: &str, png: &[u8]) -> BdgFont
+{
+    let manifest = parse_manifest(manifest, name)
+        .unwrap_or_else(|e| panic!("built-in font: {}", e));
+    font_from_manifest_png(&manifest, png, name)
+        .unwrap_or_else(|e| panic!("built-in font: {}", e))
+}
+
+/// The Apple II 40 column font: ASCII plus box drawing and block
+/// elements, U+2500 to U+259F.
+pub fn apple2_40col() -> BdgFont
+{
+    let mut font = embedded_font("40col", APPLE2_40COL_TOML, APPLE2_40COL_PNG);
+    let hi = embedded_font("40col_hi", APPLE2_40COL_HI_TOML, APPLE2_40COL_HI_PNG);
+    font.add_pages_from(&hi)
+        .unwrap_or_else(|e| panic!("built-in font: {}", e));
+    font
+}
+
+/// The Apple II 80 column font, 6x17 cells, ASCII only.
+pub fn apple2_80col() -> BdgFont
+{
+    embedded_font("80col", APPLE2_80COL_TOML, APPLE2_80COL_PNG)
+}
+
+/// The 8x8 NES arcade font, ASCII only.
+pub fn nes() -> BdgFont
+{
+    embedded_font("nes", NES_TOML, NES_PNG)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn builtins_have_the_expected_cell_size()
+    {
+        let builtins = [(apple2_40col(), (6, 8)), (apple2_80col(), (6, 17)), (nes(), (8, 8))];
+        for (font, cell) in builtins {
+            assert_eq!((font.width, font.height), cell, "{}", font.name());
+            assert!(font.glyph_rect('A').is_some(), "{}", font.name());
+        }
+        // the box drawing page is merged into the 40 column font
+        assert!(apple2_40col().glyph_rect('\u{2588}').is_some());
+    }
+}
@@ -0,0 +1,240 @@
+//! Font manifests: a small TOML file next to each atlas PNG that says how
+//! to cut it into glyphs.
+//!
+//! ```toml
+//! name = "Apple II 40 column"
+//! cell_width = 6
+//! cell_height = 8
+//! columns = 16
+//! baseline = 7
+//! ranges = [[0x20, 0x7f]]
+//! ```
+//!
+//! `ranges` lists inclusive codepoint ranges that fill the atlas one after
+//! another, starting at the top left cell. `image` names the atlas relative
+//! to the manifest and defaults to the manifest's own name with `.png`.
+//! `padding_x`, `padding_y`, `spacing_x` and `spacing_y` are optional and
+//! default to zero.
+
+use macroquad::prelude::*;
+use serde::Deserialize;
+
+use crate::font::{AtlasLayout, BdgFont, FontAtlas, FontData, FontError, FontPage};
+
+#[derive(Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct FontManifest {
+    pub name: String,
+    pub cell_width: u32,
+    pub cell_height: u32,
+    pub columns: u32,
+    pub baseline: Option<u32>,
+    pub ranges: Vec<(u32, u32)>,
+    pub image: Option<String>,
+
+    #[serde(default)]
+    pub padding_x: u32,
+    #[serde(default)]
+    pub padding_y: u32,
+    #[serde(default)]
+    pub spacing_x: u32,
+    #[serde(default)]
+    pub spacing_y: u32,
+}
+
+impl FontManifest {
+    /// How many cells the ranges fill, or `None` if it overflows.
+    pub fn cell_count(&self) -> Option<u32>
+    {
+        self.ranges.iter().try_fold(0u32, |total, &(first, last)| {
+            total.checked_add(last.checked_sub(first)?.checked_add(1)?)
+        })
+    }
+
+    /// The smallest atlas in pixels that holds every cell, or `None` if it
+    /// overflows.
+    pub fn atlas_size(&self) -> Option<(u32, u32)>
+    {
+        if self.columns == 0 {
+            return None;
+        }
+        let rows = self.cell_count()?.div_ceil(self.columns);
+        let extent = |padding: u32, count: u32, cell: u32, spacing: u32| {
+            cell.checked_add(spacing)?.checked_mul(count)?
+                .checked_add(padding)?
+                .checked_sub(spacing)
+        };
+        Some((extent(self.padding_x, self.columns, self.cell_width, self.spacing_x)?,
+              extent(self.padding_y, rows, self.cell_height, self.spacing_y)?))
+    }
+}
+
+/// Parses and sanity-checks a manifest. `path` is only used in errors.
+pub fn parse_manifest(text: &str, path: &str) -> Result<FontManifest, FontError>
+{
+    let manifest: FontManifest = toml::from_str(text).map_err(|e| FontError::Format {
+        path: path.to_string(),
+        message: e.to_string(),
+    })?;
+
+    let bad = |message: String| FontError::Format { path: path.to_string(), message };
+
+    if manifest.cell_width == 0 || manifest.cell_height == 0 {
+        return Err(bad(format!("cell size {}x{} is empty",
+                               manifest.cell_width, manifest.cell_height)));
+    }
+    if manifest.columns == 0 {
+        return Err(bad("columns must be at least 1".to_string()));
+    }
+    if manifest.ranges.is_empty() {
+        return Err(bad("no codepoint ranges".to_string()));
+    }
+    for &(first, last) in &manifest.ranges {
+        if first > last {
+            return Err(bad(format!("range {:#x}..{:#x} is backwards", first, last)));
+        }
+        if last > char::MAX as u32 {
+            return Err(bad(format!("range {:#x}..{:#x} goes past U+10FFFF", first, last)));
+        }
+    }
+    if manifest.atlas_size().is_none() {
+        return Err(bad("atlas size overflows".to_string()));
+    }
+    if let Some(baseline) = manifest.baseline {
+        if baseline > manifest.cell_height {
+            return Err(bad(format!("baseline {} is below the {} pixel cell",
+                                   baseline, manifest.cell_height)));
+        }
+    }
+
+    Ok(manifest)
+}
+
+/// Builds a font from a parsed manifest and its atlas.
+pub fn font_from_manifest(manifest: &FontManifest, atlas: &FontAtlas) -> BdgFont
+{
+    let mut pages = vec![];
+    let mut next_cell = 0;
+    for &(first, last) in &manifest.ranges {
+        let layout = AtlasLayout {
+            columns: manifest.columns,
+            first_char: first,
+            last_char: last,
+            first_cell: next_cell,
+            padding_x: manifest.padding_x,
+            padding_y: manifest.padding_y,
+            spacing_x: manifest.spacing_x,
+            spacing_y: manifest.spacing_y,
+        };
+        pages.push(FontPage { atlas: atlas.clone(), layout, cell_map: None });
+        next_cell = next_cell.saturating_add((last - first).saturating_add(1));
+    }
+
+    BdgFont::from(FontData {
+        name: manifest.name.clone(),
+        width: manifest.cell_width,
+        height: manifest.cell_height,
+        baseline: manifest.baseline.unwrap_or(manifest.cell_height),
+        pages,
+        replacement: Some('?'),
+    })
+}
+
+/// Loads `<path>.toml` and the atlas it describes, e.g.
+/// `load_font("assets/40col").await`.
+pub async fn load_font(path: &str) -> Result<BdgFont, FontError>
+{
+    let manifest_path = format!("{}.toml", path);
+    let text = load_string(&manifest_path).await
+        .map_err(|source| FontError::File { path: manifest_path.clone(), source })?;
+    let manifest = parse_manifest(&text, &manifest_path)?;
+
+    let image_path = match &manifest.image {
+        Some(image) => match path.rfind('/') {
+            Some(slash) => format!("{}/{}", &path[..slash], image),
+            None => image.clone(),
+        },
+        None => format!("{}.png", path),
+    };
+    let bytes = load_file(&image_path).await
+        .map_err(|source| FontError::File { path: image_path.clone(), source })?;
+
+    font_from_manifest_png(&manifest, &bytes, &image_path)
+}
+
+/// Decodes the atlas PNG for a manifest and checks it is big enough. `image_path` is only used in errors.
+pub fn font_from_manifest_png(manifest: &FontManifest, png: &[u8],
+                              image_path: &str) -> Result<BdgFont, FontError>
+{
+    let image = Image::from_file_with_format(png, Some(ImageFormat::Png))
+        .map_err(|source| FontError::Image { path: image_path.to_string(), source })?;
+
+    let (needed_w, needed_h) = manifest.atlas_size().ok_or_else(|| FontError::Format {
+        path: image_path.to_string(),
+        message: "atlas size overflows".to_string(),
+    })?;
+    if needed_w > image.width as u32 || needed_h > image.height as u32 {
+        return Err(FontError::Format {
+            path: image_path.to_string(),
+            message: format!("atlas is {}x{} but the manifest needs {}x{}",
+                             image.width, image.height, needed_w, needed_h),
+        });
+    }
+
+    Ok(font_from_manifest(manifest, &FontAtlas::new(image)))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn manifest(extra: &str) -> String
+    {
+        format!("name = \"test\"\ncell_width = 6\ncell_height = 8\ncolumns = 16\n{}", extra)
+    }
+
+    fn format_error(text: &str) -> String
+    {
+        match parse_manifest(text, "test.toml") {
+            Err(FontError::Format { message, .. }) => message,
+            other => panic!("expected a format error, got {:?}", other),
+        }
+    }
+
+    #[test]
+    fn atlas_size_covers_every_range()
+    {
+        let m = parse_manifest(&manifest("ranges = [[0x20, 0x7f], [0x2500, 0x2501]]\nspacing_x = 1\npadding_y = 2"),
+                               "test.toml").unwrap();
+        assert_eq!(m.cell_count(), Some(98));
+        // 7 rows of 16, the last one partly filled
+        assert_eq!(m.atlas_size(), Some((16 * 7 - 1, 2 + 7 * 8)));
+
+        let font = font_from_manifest(&m, &FontAtlas::new(Image::empty()));
+        assert_eq!(font.pages[1].layout.first_cell, 96);
+    }
+
+    #[test]
+    fn codepoints_past_unicode_are_rejected()
+    {
+        let message = format_error(&manifest("ranges = [[0x10fff0, 0x110000]]"));
+        assert!(message.contains("U+10FFFF"), "{}", message);
+        assert!(parse_manifest(&manifest("ranges = [[0x10fff0, 0x10ffff]]"), "test.toml").is_ok());
+    }
+
+    #[test]
+    fn overflowing_sizes_are_rejected()
+    {
+        let wide = "name = \"test\"\ncell_width = 0x10000000\ncell_height = 8\ncolumns = 16\nranges = [[0, 1]]";
+        assert_eq!(format_error(wide), "atlas size overflows");
+
+        let ranges = vec!["[0, 0x10ffff]"; 4000].join(", ");
+        assert_eq!(format_error(&manifest(&format!("ranges = [{}]", ranges))), "atlas size overflows");
+    }
+
+    #[test]
+    fn backwards_ranges_are_rejected()
+    {
+        assert!(format_error(&manifest("ranges = [[0x7f, 0x20]]")).contains("backwards"));
+    }
+}
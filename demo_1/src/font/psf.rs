@@ -0,0 +1,289 @@
+//! Linux console fonts in PC Screen Font format, versions 1 and 2.
+//!
+//! Glyphs without a Unicode table are mapped to the codepoint matching
+//! their index. Multi-codepoint sequences in the table are skipped, since a
+//! panel cell only ever holds one `char`.
+
+use macroquad::prelude::*;
+
+use crate::font::bitmap::{font_from_glyphs, GlyphSet};
+use crate::font::{BdgFont, FontError};
+
+const PSF1_MAGIC: [u8; 2] = [0x36, 0x04];
+const PSF1_MODE512: u8 = 0x01;
+const PSF1_MODEHASTAB: u8 = 0x02;
+const PSF1_MODESEQ: u8 = 0x04;
+const PSF1_SEPARATOR: u16 = 0xffff;
+const PSF1_STARTSEQ: u16 = 0xfffe;
+
+const PSF2_MAGIC: [u8; 4] = [0x72, 0xb5, 0x4a, 0x86];
+const PSF2_HAS_UNICODE_TABLE: u32 = 0x01;
+const PSF2_SEPARATOR: u8 = 0xff;
+const PSF2_STARTSEQ: u8 = 0xfe;
+
+/// Parses a PSF1 or PSF2 font. `path` is only used for the name and in
+/// errors.
+pub fn parse_psf(bytes: &[u8], path: &str) -> Result<GlyphSet, FontError>
+{
+    if bytes.starts_with(&PSF1_MAGIC) {
+        parse_psf1(bytes, path)
+    } else if bytes.starts_with(&PSF2_MAGIC) {
+        parse_psf2(bytes, path)
+    } else {
+        Err(bad(path, "not a PSF1 or PSF2 font".to_string()))
+    }
+}
+
+/// Loads a `.psf` file and uploads it as a font.
+pub async fn load_psf_font(path: &str) -> Result<BdgFont, FontError>
+{
+    let bytes = load_file(path).await
+        .map_err(|source| FontError::File { path: path.to_string(), source })?;
+    let glyphs = parse_psf(&bytes, path)?;
+    font_from_glyphs(&glyphs)
+}
+
+fn bad(path: &str, message: String) -> FontError
+{
+    FontError::Format { path: path.to_string(), message }
+}
+
+fn font_name(path: &str) -> &str
+{
+    let file = path.rsplit('/').next().unwrap_or(path);
+    file.split('.').next().unwrap_or(file)
+}
+
+fn glyph_set(path: &str, width: u32, height: u32) -> Result<GlyphSet, FontError>
+{
+    GlyphSet::new(font_name(path), width, height)
+        .ok_or_else(|| bad(path, format!("{}x{} glyphs are too big", width, height)))
+}
+
+fn read_u32(bytes: &[u8], offset: usize) -> u32
+{
+    u32::from_le_bytes([bytes[offset], bytes[offset + 1], bytes[offset + 2], bytes[offset + 3]])
+}
+
+/// Copies `count` glyphs of `width` x `height` pixels, `stride` bytes each
+/// with rows padded to whole bytes, out of `data`.
+fn read_glyphs(glyphs: &mut GlyphSet, data: &[u8], count: usize, stride: usize)
+{
+    let row_bytes = glyphs.width.div_ceil(8) as usize;
+    for i in 0..count {
+        let glyph = glyphs.add_glyph();
+        let bitmap = &data[i * stride..(i + 1) * stride];
+        for y in 0..glyphs.height {
+            for x in 0..glyphs.width {
+                let byte = bitmap[y as usize * row_bytes + x as usize / 8];
+                let on = byte & (0x80 >> (x % 8)) != 0;
+                glyphs.set_pixel(glyph, x, y, on);
+            }
+        }
+    }
+}
+
+fn map_by_index(glyphs: &mut GlyphSet)
+{
+    for i in 0..glyphs.glyphs.len() as u32 {
+        if let Some(c) = char::from_u32(i) {
+            glyphs.char_map.insert(c, i);
+        }
+    }
+}
+
+fn parse_psf1(bytes: &[u8], path: &str) -> Result<GlyphSet, FontError>
+{
+    if bytes.len() < 4 {
+        return Err(bad(path, "truncated PSF1 header".to_string()));
+    }
+    let mode = bytes[2];
+    let height = bytes[3] as u32;
+    let count: usize = if mode & PSF1_MODE512 != 0 { 512 } else { 256 };
+    let stride = height as usize;
+
+    let glyph_end = count.checked_mul(stride).and_then(|size| size.checked_add(4));
+    let Some(glyph_end) = glyph_end.filter(|&end| height != 0 && bytes.len() >= end) else {
+        return Err(bad(path, format!("expected {} glyphs of 8x{}", count, height)));
+    };
+
+    let mut glyphs = glyph_set(path, 8, height)?;
+    read_glyphs(&mut glyphs, &bytes[4..], count, stride);
+
+    if mode & (PSF1_MODEHASTAB | PSF1_MODESEQ) == 0 {
+        map_by_index(&mut glyphs);
+        return Ok(glyphs);
+    }
+
+    let table = &bytes[glyph_end..];
+    let mut entries = table.chunks_exact(2).map(|pair| u16::from_le_bytes([pair[0], pair[1]]));
+    for glyph in 0..count as u32 {
+        let mut in_sequence = false;
+        loop {
+            match entries.next() {
+                None => {
+                    return Err(bad(path, format!("Unicode table ends at glyph {}", glyph)));
+                }
+                Some(PSF1_SEPARATOR) => break,
+                Some(PSF1_STARTSEQ) => in_sequence = true,
+                Some(unit) => {
+                    if !in_sequence {
+                        if let Some(c) = char::from_u32(unit as u32) {
+                            glyphs.char_map.entry(c).or_insert(glyph);
+                        }
+                    }
+                }
+            }
+        }
+    }
+
+    Ok(glyphs)
+}
+
+fn parse_psf2(bytes: &[u8], path: &str) -> Result<GlyphSet, FontError>
+{
+    if bytes.len() < 32 {
+        return Err(bad(path, "truncated PSF2 header".to_string()));
+    }
+    let header_size = read_u32(bytes, 8) as usize;
+    let flags = read_u32(bytes, 12);
+    let count = read_u32(bytes, 16) as usize;
+    let stride = read_u32(bytes, 20) as usize;
+    let height = read_u32(bytes, 24);
+    let width = read_u32(bytes, 28);
+
+    if header_size < 32 {
+        return Err(bad(path, format!("header size {} is smaller than the header", header_size)));
+    }
+    let glyph_bytes = (width.div_ceil(8) as usize).checked_mul(height as usize);
+    if width == 0 || height == 0 || glyph_bytes.is_none_or(|needed| stride < needed) {
+        return Err(bad(path, format!("{} byte glyphs can't hold {}x{} pixels",
+                                     stride, width, height)));
+    }
+    let glyph_end = count.checked_mul(stride).and_then(|size| size.checked_add(header_size));
+    let Some(glyph_end) = glyph_end.filter(|&end| bytes.len() >= end) else {
+        return Err(bad(path, format!("expected {} glyphs of {}x{}", count, width, height)));
+    };
+
+    let mut glyphs = glyph_set(path, width, height)?;
+    read_glyphs(&mut glyphs, &bytes[header_size..], count, stride);
+
+    if flags & PSF2_HAS_UNICODE_TABLE == 0 {
+        map_by_index(&mut glyphs);
+        return Ok(glyphs);
+    }
+
+    let mut entries = bytes[glyph_end..].split(|&b| b == PSF2_SEPARATOR);
+    for glyph in 0..count as u32 {
+        let Some(entry) = entries.next() else {
+            return Err(bad(path, format!("Unicode table ends at glyph {}", glyph)));
+        };
+        let singles = entry.split(|&b| b == PSF2_STARTSEQ).next().unwrap_or(&[]);
+        let text = std::str::from_utf8(singles)
+            .map_err(|_| bad(path, format!("bad UTF-8 for glyph {}", glyph)))?;
+        for c in text.chars() {
+            glyphs.char_map.entry(c).or_insert(glyph);
+        }
+    }
+
+    Ok(glyphs)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn psf2(header_size: u32, count: u32, stride: u32, height: u32, width: u32) -> Vec<u8>
+    {
+        let mut bytes = PSF2_MAGIC.to_vec();
+        for field in [0, header_size, 0, count, stride, height, width] {
+            bytes.extend(field.to_le_bytes());
+        }
+        bytes
+    }
+
+    fn error(bytes: &[u8]) -> String
+    {
+        match parse_psf(bytes, "test.psf") {
+            Err(FontError::Format { message, .. }) => message,
+            other => panic!("expected a format error, got {:?}", other),
+        }
+    }
+
+    #[test]
+    fn psf2_glyphs_are_read()
+    {
+        let mut bytes = psf2(32, 2, 2, 2, 3);
+        bytes.extend([0b1010_0000, 0b0100_0000, 0b1110_0000, 0]);
+        let glyphs = parse_psf(&bytes, "fonts/tiny.psfu").unwrap();
+
+        assert_eq!(glyphs.name, "tiny");
+        assert_eq!((glyphs.width, glyphs.height), (3, 2));
+        assert_eq!(glyphs.glyphs[0], [true, false, true, false, true, false]);
+        assert_eq!(glyphs.glyphs[1], [true, true, true, false, false, false]);
+        assert_eq!(glyphs.char_map[&'\u{1}'], 1);
+    }
+
+    #[test]
+    fn truncated_headers_are_rejected()
+    {
+        assert_eq!(error(&psf2(32, 1, 8, 8, 8)[..20]), "truncated PSF2 header");
+        assert_eq!(error(&PSF1_MAGIC), "truncated PSF1 header");
+        assert_eq!(error(b"BDF"), "not a PSF1 or PSF2 font");
+    }
+
+    #[test]
+    fn truncated_glyphs_are_rejected()
+    {
+        let mut bytes = psf2(32, 2, 8, 8, 8);
+        bytes.extend([0; 15]);
+        assert_eq!(error(&bytes), "expected 2 glyphs of 8x8");
+
+        let mut bytes = vec![PSF1_MAGIC[0], PSF1_MAGIC[1], 0, 8];
+        bytes.extend([0; 256 * 8 - 1]);
+        assert_eq!(error(&bytes), "expected 256 glyphs of 8x8");
+        bytes.push(0);
+        assert!(parse_psf(&bytes, "test.psf").is_ok());
+    }
+
+    #[test]
+    fn corrupt_headers_are_rejected()
+    {
+        assert!(error(&psf2(4, 1, 8, 8, 8)).contains("header size 4"));
+        assert!(error(&psf2(32, 1, 1, 8, 8)).contains("can't hold 8x8"));
+        assert!(error(&psf2(32, 1, 8, 0, 8)).contains("can't hold 8x0"));
+        assert!(error(&psf2(u32::MAX, u32::MAX, u32::MAX, 8, 8)).starts_with("expected"));
+
+        let mut bytes = psf2(32, 1, 300, 300, 8);
+        bytes.extend([0; 300]);
+        assert_eq!(error(&bytes), "8x300 glyphs are too big");
+    }
+
+    #[test]
+    fn unicode_tables_map_characters_to_glyphs()
+    {
+        let mut bytes = psf2(32, 3, 1, 1, 1);
+        bytes[12] = PSF2_HAS_UNICODE_TABLE as u8;
+        bytes.extend([0x80, 0x00, 0x80]);
+        // glyph 0 is 'A' and 'a', glyph 1 only a sequence, glyph 2 is '\u{2588}'
+        bytes.extend(b"Aa\xff\xfeAB\xff\xe2\x96\x88\xff");
+        let glyphs = parse_psf(&bytes, "test.psf").unwrap();
+
+        assert_eq!(glyphs.char_map.len(), 3);
+        assert_eq!(glyphs.char_map[&'A'], 0);
+        assert_eq!(glyphs.char_map[&'a'], 0);
+        assert_eq!(glyphs.char_map[&'\u{2588}'], 2);
+        assert!(!glyphs.char_map.contains_key(&'B'));
+        assert_eq!(glyphs.glyphs[2], [true]);
+    }
+
+    #[test]
+    fn short_unicode_tables_are_rejected()
+    {
+        let mut bytes = psf2(32, 2, 1, 1, 1);
+        bytes[12] = PSF2_HAS_UNICODE_TABLE as u8;
+        bytes.extend([0x80, 0x00]);
+        bytes.extend(b"A");
+        assert_eq!(error(&bytes), "Unicode table ends at glyph 1");
+    }
+}
@@ -0,0 +1,113 @@
+//! Fonts looked up by name at runtime, e.g. for a settings menu that lets
+//! the player pick a font.
+
+use std::collections::HashMap;
+
+use crate::font::{builtin, BdgFont};
+
+/// Named font handles, kept in the order they were registered.
+#[derive(Debug, Clone, Default)]
+pub struct FontRegistry {
+    fonts: HashMap<String, BdgFont>,
+    names: Vec<String>,
+}
+
+impl FontRegistry {
+    pub fn new() -> FontRegistry
+    {
+        FontRegistry::default()
+    }
+
+    /// A registry holding the built-in fonts as "apple2-40", "apple2-80"
+    /// and "nes".
+    pub fn with_builtins() -> FontRegistry
+    {
+        let mut registry = FontRegistry::new();
+        registry.register("apple2-40", builtin::apple2_40col());
+        registry.register("apple2-80", builtin::apple2_80col());
+        registry.register("nes", builtin::nes());
+        registry
+    }
+
+    /// Adds `font` under `name`, replacing any font already using that
+    /// name but keeping its place in the order.
+    pub fn register(&mut self, name: &str, font: BdgFont)
+    {
+        if self.fonts.insert(name.to_string(), font).is_none() {
+            self.names.push(name.to_string());
+        }
+    }
+
+    pub fn remove(&mut self, name: &str) -> Option<BdgFont>
+    {
+        self.names.retain(|n| n != name);
+        self.fonts.remove(name)
+    }
+
+    /// A handle to the font registered as `name`.
+    pub fn get(&self, name: &str) -> Option<BdgFont>
+    {
+        self.fonts.get(name).cloned()
+    }
+
+    pub fn contains(&self, name: &str) -> bool
+    {
+        self.fonts.contains_key(name)
+    }
+
+    /// Registered names, oldest first.
+    pub fn names(&self) -> impl Iterator<Item = &str>
+    {
+        self.names.iter().map(|n| n.as_str())
+    }
+
+    pub fn len(&self) -> usize
+    {
+        self.names.len()
+    }
+
+    pub fn is_empty(&self) -> bool
+    {
+        self.names.is_empty()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::font::make_font;
+    use crate::font::tests::texture;
+
+    #[test]
+    fn fonts_are_found_by_name()
+    {
+        let mut registry = FontRegistry::new();
+        assert!(registry.is_empty());
+        let small = make_font(&texture(), 6, 8);
+        registry.register("small", small.clone());
+        registry.register("big", make_font(&texture(), 8, 16));
+
+        assert!(registry.get("small").unwrap().ptr_eq(&small));
+        assert_eq!(registry.get("big").unwrap().height, 16);
+        assert!(registry.get("medium").is_none());
+        assert!(!registry.contains("medium"));
+        assert_eq!(registry.names().collect::<Vec<_>>(), ["small", "big"]);
+
+        assert!(registry.remove("small").is_some());
+        assert_eq!(registry.names().collect::<Vec<_>>(), ["big"]);
+        assert!(registry.remove("small").is_none());
+    }
+
+    #[test]
+    fn duplicate_names_replace_the_font_in_place()
+    {
+        let mut registry = FontRegistry::new();
+        registry.register("a", make_font(&texture(), 6, 8));
+        registry.register("b", make_font(&texture(), 6, 8));
+        registry.register("a", make_font(&texture(), 8, 8));
+
+        assert_eq!(registry.len(), 2);
+        assert_eq!(registry.names().collect::<Vec<_>>(), ["a", "b"]);
+        assert_eq!(registry.get("a").unwrap().width, 8);
+    }
+}
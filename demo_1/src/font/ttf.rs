@@ -0,0 +1,141 @@
+//! Rasterizing TrueType and OpenType fonts into bitmap fonts at runtime,
+//! the same job `Tools/makefont.py` does offline.
+//!
+//! Coverage is hard-thresholded, so the result has no antialiasing and
+//! looks like a hand-made bitmap font when the pixel size suits the face.
+
+use fontdue::{Font, FontSettings};
+
+use crate::font::bitmap::{font_from_glyphs, GlyphSet, MAX_GLYPH_SIZE};
+use crate::font::{BdgFont, FontError};
+
+/// How to turn an outline font into cells. The fields mirror the
+/// parameters of `makefont()` in `Tools/makefont.py`.
+#[derive(Debug, Clone)]
+pub struct TtfOptions {
+    /// Em size in pixels, like PIL's `fontsize`.
+    pub pixel_size: f32,
+    pub cell_width: u32,
+    pub cell_height: u32,
+
+    /// Shifts every glyph within its cell. With both at zero the top of
+    /// the font's ascender sits on the top edge of the cell.
+    pub x_offset: i32,
+    pub y_offset: i32,
+
+    /// Inclusive codepoint ranges to rasterize. Codepoints the font has no
+    /// glyph for are left out.
+    pub ranges: Vec<(u32, u32)>,
+
+    /// Coverage, 0 to 255, at or above which a pixel is set.
+    pub threshold: u8,
+}
+
+impl TtfOptions {
+    /// Printable ASCII with a 50% coverage threshold and no offsets.
+    pub fn new(pixel_size: f32, cell_width: u32, cell_height: u32) -> TtfOptions
+    {
+        TtfOptions {
+            pixel_size,
+            cell_width,
+            cell_height,
+            x_offset: 0,
+            y_offset: 0,
+            ranges: vec![(0x20, 0x7f)],
+            threshold: 128,
+        }
+    }
+}
+
+/// Rasterizes the glyphs in `options.ranges` from TTF or OTF `bytes`.
+/// `path` is only used in errors.
+pub fn rasterize_ttf(bytes: &[u8], options: &TtfOptions, path: &str) -> Result<GlyphSet, FontError>
+{
+    let bad = |message: String| FontError::Format { path: path.to_string(), message };
+
+    if !(1..=MAX_GLYPH_SIZE).contains(&options.cell_width)
+        || !(1..=MAX_GLYPH_SIZE).contains(&options.cell_height) {
+        return Err(bad(format!("cell size {}x{} isn't between 1x1 and {}x{}",
+                               options.cell_width, options.cell_height,
+                               MAX_GLYPH_SIZE, MAX_GLYPH_SIZE)));
+    }
+    let font = Font::from_bytes(bytes, FontSettings::default())
+        .map_err(|e| bad(e.to_string()))?;
+
+    let ascent = font.horizontal_line_metrics(options.pixel_size)
+        .map(|m| m.ascent.round() as i32)
+        .unwrap_or(options.pixel_size.round() as i32);
+    let baseline = ascent + options.y_offset;
+
+    let name = font.name().unwrap_or(path);
+    let mut glyphs = GlyphSet::new(name, options.cell_width, options.cell_height)
+        .expect("cell size was checked above");
+    glyphs.baseline = baseline.clamp(0, options.cell_height as i32) as u32;
+
+    for &(first, last) in &options.ranges {
+        for c in (first..=last).filter_map(char::from_u32) {
+            if font.lookup_glyph_index(c) == 0 {
+                continue;
+            }
+            let (metrics, coverage) = font.rasterize(c, options.pixel_size);
+            let glyph = glyphs.add_glyph();
+            glyphs.char_map.entry(c).or_insert(glyph);
+
+            let left = options.x_offset + metrics.xmin;
+            let top = baseline - (metrics.ymin + metrics.height as i32);
+            for row in 0..metrics.height {
+                for col in 0..metrics.width {
+                    if coverage[row * metrics.width + col] < options.threshold {
+                        continue;
+                    }
+                    let x = left + col as i32;
+                    let y = top + row as i32;
+                    if x >= 0 && y >= 0 {
+                        glyphs.set_pixel(glyph, x as u32, y as u32, true);
+                    }
+                }
+            }
+        }
+    }
+
+    Ok(glyphs)
+}
+
+/// Rasterizes a TTF or OTF font and uploads it as a font.
+pub fn make_ttf_font(bytes: &[u8], options: &TtfOptions) -> Result<BdgFont, FontError>
+{
+    let glyphs = rasterize_ttf(bytes, options, "<ttf>")?;
+    font_from_glyphs(&glyphs)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const PROGGY: &[u8] = include_bytes!("../../assets/ProggyClean.ttf");
+
+    #[test]
+    fn glyphs_are_rasterized_into_cells()
+    {
+        let glyphs = rasterize_ttf(PROGGY, &TtfOptions::new(13.0, 7, 13), "ProggyClean.ttf").unwrap();
+        assert_eq!((glyphs.width, glyphs.height), (7, 13));
+        assert!(glyphs.baseline > 0 && glyphs.baseline <= 13);
+
+        let a = &glyphs.glyphs[glyphs.char_map[&'A'] as usize];
+        assert_eq!(a.len(), 7 * 13);
+        assert!(a.iter().any(|&on| on));
+        let space = &glyphs.glyphs[glyphs.char_map[&' '] as usize];
+        assert!(space.iter().all(|&on| !on));
+    }
+
+    #[test]
+    fn bad_cell_sizes_are_rejected()
+    {
+        for (width, height) in [(0, 13), (7, 0), (MAX_GLYPH_SIZE + 1, 13), (7, u32::MAX)] {
+            let options = TtfOptions::new(13.0, width, height);
+            assert!(matches!(rasterize_ttf(PROGGY, &options, "ProggyClean.ttf"),
+                             Err(FontError::Format { .. })));
+        }
+        assert!(rasterize_ttf(b"not a font", &TtfOptions::new(13.0, 7, 13), "junk.ttf").is_err());
+    }
+}
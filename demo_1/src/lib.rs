@@ -1,2 +1,13 @@
 pub mod panel;
+pub mod surface;
+pub mod cursor;
+pub mod mouse;
+pub mod palette;
 pub mod font;
+pub mod ansi;
+mod cache;
+pub mod mesh;
+pub mod snapshot;
+pub mod software;
+#[cfg(target_os = "linux")]
+pub mod terminal;
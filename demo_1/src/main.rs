@@ -1,8 +1,8 @@
 use macroquad::prelude::*;
 use quad_snd::{AudioContext, Sound};
 
-mod panel;
-mod font;
+use demo_1::panel;
+use demo_1::font;
 
 pub struct FontRecord {
     pub cell_width: u32,
@@ -42,52 +42,6 @@ fn draw_string(s: &str, color: Color, x: f32, y: f32, texture: &Texture2D, font_
     }
 }
 
-fn draw_box(color: Color, x: f32, y: f32, width: u8, height: u8,
-            texture: &Texture2D, font_record: &FontRecord)
-{
-    for cx in 1..(width-1) {
-        draw_char('-', color,
-                  x + cx as f32 * font_record.cell_width as f32,
-                  y,
-                  &texture, &font_record);
-        draw_char('-', color,
-                  x + cx as f32 * font_record.cell_width as f32,
-                  y + (height - 1) as f32 * font_record.cell_height as f32,
-                  &texture, &font_record);
-    }
-    for cy in 1..(height-1) {
-        draw_char('|', color,
-                  x,
-                  y + cy as f32 * font_record.cell_height as f32,
-                  &texture, &font_record);
-        draw_char('|', color,
-                  x + (width - 1) as f32 * font_record.cell_width as f32,
-                  y + cy as f32 * font_record.cell_height as f32,
-                  &texture, &font_record);
-
-    }
-    draw_char('+', color,
-              x, y,
-              &texture, &font_record);
-                  
-    draw_char('+', color,
-              x + (width - 1) as f32 * font_record.cell_width as f32,
-              y,
-              &texture, &font_record);
-                  
-    draw_char('+', color,
-              x,
-              y + (height - 1) as f32 * font_record.cell_height as f32,
-              &texture, &font_record);
-                  
-    draw_char('+', color,
-              x + (width - 1) as f32 * font_record.cell_width as f32,
-              y + (height - 1) as f32 * font_record.cell_height as f32,
-              &texture, &font_record);
-                  
-        
-}
-
 #[macroquad::main("Demo1")]
 async fn main() {
     println!("Hello, world!");
@@ -110,11 +64,17 @@ async fn main() {
     
     beep_sound.play(&mut audio_ctx, Default::default());
 
-    let a2_font_obj = font::make_font();
+    let a2_font_obj = font::make_font(&a2_font, 6, 8);
     
-    let my_panel = panel::make_panel(100.0, 100.0,
-                                     Some(BLACK),
-                                     a2_font_obj, 10, 10);
+    let mut my_panel = panel::Panel::builder(a2_font_obj, 10, 10)
+        .position(100.0, 100.0)
+        .font_color(GREEN)
+        .erase_color(Some(BLACK))
+        .scale(1)
+        .build();
+
+    panel::boxes::draw_box(&mut my_panel, 0, 0, 10, 4,
+                           &panel::boxes::BoxOptions::new(panel::boxes::BorderStyle::Ascii));
 
     loop {
         clear_background(RED);
@@ -145,9 +105,8 @@ async fn main() {
 
         draw_string("Hello, NES World!", BLACK, 0.0, 160.0, &nes_font, &nes_font_record);
 
-        draw_box(BLACK, 10.0, 180.0, 10, 4, &a2_font, &a2_font_record);
 
-        panel::draw_panel(&my_panel);
+        my_panel.draw();
         next_frame().await
     }
 }
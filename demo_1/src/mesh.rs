@@ -0,0 +1,198 @@
+//! Drawing a panel as a few meshes instead of a quad per cell.
+//!
+//! Backgrounds go in one mesh, the glyphs from each font page in another
+//! and underlines in a third, so a panel costs a handful of draw calls
+//! however big it is. Cells don't overlap, so drawing all backgrounds
+//! before all glyphs looks the same as drawing cell by cell.
+
+use macroquad::models::{draw_mesh, Mesh, Vertex};
+use macroquad::prelude::*;
+
+use crate::cursor::cursor_paint;
+use crate::palette::Palette;
+use crate::panel::{resolve_cell_colors, Attrs, Panel};
+
+/// Quads per mesh. macroquad clamps a single draw to 5000 indices by
+/// default, so bigger batches are split.
+const MAX_QUADS: usize = 800;
+
+/// The meshes for one frame of a panel, in drawing order.
+pub struct PanelMesh {
+    meshes: Vec<Mesh>,
+}
+
+impl PanelMesh {
+    /// Builds the meshes for `panel` as it looks when the flash clock is
+    /// in phase `flash_on`, with its palette cycled to the current time.
+    /// Spaces with no background are left out.
+    pub fn build(panel: &Panel, flash_on: bool) -> PanelMesh
+    {
+        PanelMesh::build_cells(panel, flash_on, &panel.palette_at(get_time()),
+                               vec2(panel.screen_x, panel.screen_y), None)
+    }
+
+    /// Builds the meshes for the panel with its top left corner at
+    /// `origin`, looking up indexed colors in `palette`. With `only` set,
+    /// just those cells are included, each with the erase color drawn
+    /// behind it rather than across the whole panel.
+    pub fn build_cells(panel: &Panel, flash_on: bool, palette: &Palette, origin: Vec2,
+                       only: Option<&[(u32, u32)]>) -> PanelMesh
+    {
+        let cell_w = (panel.font.width * panel.pixel_scale) as f32;
+        let cell_h = (panel.font.height * panel.pixel_scale) as f32;
+        let scale = panel.pixel_scale as f32;
+        let underline_y = panel.font.underline_row() as f32 * scale;
+        let solid = Rect::new(0.0, 0.0, 1.0, 1.0);
+
+        let mut backgrounds = vec![];
+        let mut glyphs: Vec<Vec<Mesh>> = panel.font.pages.iter().map(|_| vec![]).collect();
+        let mut underlines = vec![];
+
+        let all: Vec<(u32, u32)>;
+        let cells = match only {
+            Some(cells) => cells,
+            None => {
+                if let Some(erase_color) = panel.erase_color {
+                    push_quad(&mut backgrounds, None,
+                              Rect::new(origin.x, origin.y, panel.screen_width, panel.screen_height),
+                              solid, erase_color);
+                }
+                all = (0..panel.char_height)
+                    .flat_map(|y| (0..panel.char_width).map(move |x| (x, y)))
+                    .collect();
+                &all
+            }
+        };
+
+        for &(x, y) in cells {
+            let Some(cell) = panel.cells.get(y as usize).and_then(|row| row.get(x as usize)) else {
+                continue;
+            };
+            let sx = origin.x + x as f32 * cell_w;
+            let sy = origin.y + y as f32 * cell_h;
+            let (fg, bg) = resolve_cell_colors(cell, palette, panel.erase_color, flash_on);
+
+            if only.is_some() {
+                if let Some(erase_color) = panel.erase_color {
+                    push_quad(&mut backgrounds, None, Rect::new(sx, sy, cell_w, cell_h), solid, erase_color);
+                }
+            }
+            if let Some(bg) = bg {
+                push_quad(&mut backgrounds, None, Rect::new(sx, sy, cell_w, cell_h), solid, bg);
+            }
+            if cell.ch != ' ' {
+                push_glyph(&mut glyphs, panel, cell.ch, Rect::new(sx, sy, cell_w, cell_h), fg);
+            }
+            if cell.attrs.contains(Attrs::UNDERLINE) {
+                push_quad(&mut underlines, None,
+                          Rect::new(sx, sy + underline_y, cell_w, scale), solid, fg);
+            }
+        }
+
+        let mut meshes = backgrounds;
+        meshes.extend(glyphs.into_iter().flatten());
+        meshes.extend(underlines);
+        PanelMesh { meshes }
+    }
+
+    /// Builds the meshes for the panel's cursor in its on phase, with the
+    /// panel's top left corner at `origin`. Empty if the cursor is hidden.
+    pub fn build_cursor(panel: &Panel, flash_on: bool, palette: &Palette, origin: Vec2) -> PanelMesh
+    {
+        let Some(paint) = cursor_paint(panel, palette, flash_on) else {
+            return PanelMesh { meshes: vec![] };
+        };
+        let cell_w = (panel.font.width * panel.pixel_scale) as f32;
+        let cell_h = (panel.font.height * panel.pixel_scale) as f32;
+        let sx = origin.x + paint.cell.0 as f32 * cell_w;
+        let sy = origin.y + paint.cell.1 as f32 * cell_h;
+        let solid = Rect::new(0.0, 0.0, 1.0, 1.0);
+
+        let mut meshes = vec![];
+        for (rect, color) in paint.rects {
+            push_quad(&mut meshes, None, rect.offset(vec2(sx, sy)), solid, color);
+        }
+        if let Some((c, color)) = paint.glyph {
+            let mut glyphs: Vec<Vec<Mesh>> = panel.font.pages.iter().map(|_| vec![]).collect();
+            push_glyph(&mut glyphs, panel, c, Rect::new(sx, sy, cell_w, cell_h), color);
+            meshes.extend(glyphs.into_iter().flatten());
+        }
+        PanelMesh { meshes }
+    }
+
+    pub fn draw(&self)
+    {
+        for mesh in &self.meshes {
+            draw_mesh(mesh);
+        }
+    }
+}
+
+/// Appends the glyph for `c`, or the replacement glyph, to the meshes for
+/// the font page it's on.
+fn push_glyph(glyphs: &mut [Vec<Mesh>], panel: &Panel, c: char, rect: Rect, color: Color)
+{
+    let Some((page, source)) = panel.font.find_glyph_or_replacement(c) else {
+        return;
+    };
+    let index = panel.font.pages.iter()
+        .position(|p| std::ptr::eq(p, page))
+        .unwrap_or(0);
+    let texture = page.atlas.texture();
+    let uv = Rect::new(source.x / texture.width(), source.y / texture.height(),
+                       source.w / texture.width(), source.h / texture.height());
+    push_quad(&mut glyphs[index], Some(texture), rect, uv, color);
+}
+
+/// Appends a quad covering `rect` on screen and `uv` in the texture,
+/// starting a new mesh when the last one is full.
+fn push_quad(meshes: &mut Vec<Mesh>, texture: Option<&Texture2D>, rect: Rect, uv: Rect, color: Color)
+{
+    if meshes.last().is_none_or(|mesh| mesh.vertices.len() >= MAX_QUADS * 4) {
+        meshes.push(Mesh { vertices: vec![], indices: vec![], texture: texture.cloned() });
+    }
+    let Some(mesh) = meshes.last_mut() else {
+        return;
+    };
+
+    let base = mesh.vertices.len() as u16;
+    mesh.vertices.extend([
+        Vertex::new(rect.x, rect.y, 0.0, uv.x, uv.y, color),
+        Vertex::new(rect.x + rect.w, rect.y, 0.0, uv.x + uv.w, uv.y, color),
+        Vertex::new(rect.x + rect.w, rect.y + rect.h, 0.0, uv.x + uv.w, uv.y + uv.h, color),
+        Vertex::new(rect.x, rect.y + rect.h, 0.0, uv.x, uv.y + uv.h, color),
+    ]);
+    mesh.indices.extend([base, base + 1, base + 2, base, base + 2, base + 3]);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::font::make_font;
+    use crate::font::tests::texture;
+    use crate::panel::Cell;
+    use crate::surface::TextSurface;
+
+    // Only blank cells, since glyph quads would upload the font's atlas.
+    #[test]
+    fn quads_are_batched_and_split()
+    {
+        let mut panel = Panel::builder(make_font(&texture(), 6, 8), 40, 30)
+            .erase_color(Some(BLACK))
+            .build();
+        panel.set_colors(WHITE, Some(BLUE));
+        panel.clear();
+        panel.put_cell(Cell::with_attrs(' ', RED.into(), None, Attrs::UNDERLINE), 1, 0);
+
+        let mesh = PanelMesh::build_cells(&panel, false, &panel.palette, Vec2::ZERO, None);
+        let quads: Vec<usize> = mesh.meshes.iter().map(|m| m.vertices.len() / 4).collect();
+        // the erase rectangle plus every background but the underlined cell
+        assert_eq!(quads, [MAX_QUADS, 1200 - MAX_QUADS, 1]);
+
+        let underline = &mesh.meshes[2].vertices;
+        assert_eq!(underline[0].position, vec3(6.0, 7.0, 0.0));
+        assert_eq!(underline[2].position, vec3(12.0, 8.0, 0.0));
+        let red: [u8; 4] = RED.into();
+        assert_eq!(underline[0].color, red);
+    }
+}
@@ -0,0 +1,281 @@
+//! Mouse input in cell coordinates.
+//!
+//! Each frame `MouseRouter::poll` turns macroquad's mouse state into
+//! events for the topmost panel under the pointer. Once a button goes
+//! down, the panel it went down on keeps getting that button's drag and
+//! release events until it's let go, even if the pointer leaves it.
+
+use macroquad::prelude::*;
+
+use crate::panel::Panel;
+
+const BUTTONS: [MouseButton; 3] = [MouseButton::Left, MouseButton::Right, MouseButton::Middle];
+
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub enum MouseEventKind {
+    /// The pointer moved onto this cell.
+    Hover,
+    /// The pointer moved off the panel from this cell.
+    Leave,
+    Press(MouseButton),
+    Release(MouseButton),
+    /// The button was pressed and released on the same cell.
+    Click(MouseButton),
+    /// The pointer moved onto this cell with the button held.
+    Drag(MouseButton),
+    /// The wheel turned, by `x`, `y` as reported by macroquad.
+    Wheel(f32, f32),
+}
+
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub struct MouseEvent {
+    /// Index of the panel in the slice given to `poll`.
+    pub panel: usize,
+    pub x: u32,
+    pub y: u32,
+    pub kind: MouseEventKind,
+}
+
+/// The mouse as read at the start of a frame.
+#[derive(Debug, Copy, Clone, PartialEq, Default)]
+pub struct MouseState {
+    pub position: Vec2,
+    /// Left, right and middle.
+    pub down: [bool; 3],
+    pub wheel: Vec2,
+}
+
+impl MouseState {
+    pub fn current() -> MouseState
+    {
+        let (x, y) = mouse_position();
+        let (wheel_x, wheel_y) = mouse_wheel();
+        MouseState {
+            position: vec2(x, y),
+            down: BUTTONS.map(is_mouse_button_down),
+            wheel: vec2(wheel_x, wheel_y),
+        }
+    }
+}
+
+/// Tracks the mouse between frames to work out hovers, clicks and drags.
+#[derive(Default)]
+pub struct MouseRouter {
+    /// The panel and cell the pointer was over.
+    hovered: Option<(usize, (u32, u32))>,
+
+    /// Which buttons were down last frame.
+    down: [bool; 3],
+
+    /// For each button held, where it went down on a panel.
+    held: [Option<Held>; 3],
+}
+
+#[derive(Copy, Clone)]
+struct Held {
+    panel: usize,
+    pressed: (u32, u32),
+    /// The cell the last press or drag event was for.
+    last: (u32, u32),
+}
+
+impl MouseRouter {
+    pub fn new() -> MouseRouter
+    {
+        MouseRouter::default()
+    }
+
+    /// Reads the mouse and returns this frame's events. `panels` are in
+    /// drawing order, so later panels are on top; events name panels by
+    /// their index here, so pass the same panels in the same order every
+    /// frame.
+    pub fn poll(&mut self, panels: &[&Panel]) -> Vec<MouseEvent>
+    {
+        self.update(panels, MouseState::current())
+    }
+
+    /// Works out the events for a frame where the mouse is in `state`.
+    pub fn update(&mut self, panels: &[&Panel], state: MouseState) -> Vec<MouseEvent>
+    {
+        let mut events = vec![];
+        let event = |panel: usize, (x, y): (u32, u32), kind| MouseEvent { panel, x, y, kind };
+
+        let under = panels.iter().enumerate().rev()
+            .find_map(|(i, panel)| Some((i, panel.cell_at(state.position)?)));
+
+        if under != self.hovered {
+            if let Some((panel, cell)) = self.hovered {
+                if under.is_none_or(|(p, _)| p != panel) {
+                    events.push(event(panel, cell, MouseEventKind::Leave));
+                }
+            }
+            if let Some((panel, cell)) = under {
+                events.push(event(panel, cell, MouseEventKind::Hover));
+            }
+            self.hovered = under;
+        }
+
+        for (b, &button) in BUTTONS.iter().enumerate() {
+            let was_down = std::mem::replace(&mut self.down[b], state.down[b]);
+            if state.down[b] && !was_down {
+                if let Some((panel, cell)) = under {
+                    events.push(event(panel, cell, MouseEventKind::Press(button)));
+                    self.held[b] = Some(Held { panel, pressed: cell, last: cell });
+                }
+                continue;
+            }
+
+            let Some(held) = self.held[b] else {
+                continue;
+            };
+            let Some(&panel) = panels.get(held.panel) else {
+                self.held[b] = None;
+                continue;
+            };
+            let cell = clamped_cell_at(panel, state.position);
+            if state.down[b] {
+                if cell != held.last {
+                    events.push(event(held.panel, cell, MouseEventKind::Drag(button)));
+                    self.held[b] = Some(Held { last: cell, ..held });
+                }
+            } else {
+                events.push(event(held.panel, cell, MouseEventKind::Release(button)));
+                if under == Some((held.panel, held.pressed)) {
+                    events.push(event(held.panel, cell, MouseEventKind::Click(button)));
+                }
+                self.held[b] = None;
+            }
+        }
+
+        if state.wheel != Vec2::ZERO {
+            if let Some((panel, cell)) = under {
+                events.push(event(panel, cell, MouseEventKind::Wheel(state.wheel.x, state.wheel.y)));
+            }
+        }
+
+        events
+    }
+}
+
+/// The cell nearest `pos`, for drags that leave the panel.
+fn clamped_cell_at(panel: &Panel, pos: Vec2) -> (u32, u32)
+{
+    let cell_w = (panel.font.width * panel.pixel_scale).max(1) as f32;
+    let cell_h = (panel.font.height * panel.pixel_scale).max(1) as f32;
+    let x = ((pos.x - panel.screen_x) / cell_w).max(0.0) as u32;
+    let y = ((pos.y - panel.screen_y) / cell_h).max(0.0) as u32;
+    (x.min(panel.char_width.saturating_sub(1)), y.min(panel.char_height.saturating_sub(1)))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::font::builtin;
+
+    use MouseButton::Left;
+    use MouseEventKind::*;
+
+    /// 4 by 3 cells of 12 by 16 pixels, covering (10, 20) to (58, 68).
+    fn big() -> Panel
+    {
+        Panel::builder(builtin::apple2_40col(), 4, 3).position(10.0, 20.0).scale(2).build()
+    }
+
+    /// 5 by 5 cells of 6 by 8 pixels, covering (40, 40) to (70, 80).
+    fn small() -> Panel
+    {
+        Panel::builder(builtin::apple2_40col(), 5, 5).position(40.0, 40.0).build()
+    }
+
+    fn at(x: f32, y: f32) -> MouseState
+    {
+        MouseState { position: vec2(x, y), ..MouseState::default() }
+    }
+
+    fn held(x: f32, y: f32) -> MouseState
+    {
+        MouseState { down: [true, false, false], ..at(x, y) }
+    }
+
+    fn event(panel: usize, x: u32, y: u32, kind: MouseEventKind) -> MouseEvent
+    {
+        MouseEvent { panel, x, y, kind }
+    }
+
+    #[test]
+    fn hit_testing_stops_at_the_edges()
+    {
+        let panel = big();
+        let mut mouse = MouseRouter::new();
+        assert_eq!(mouse.update(&[&panel], at(9.9, 20.0)), vec![]);
+        assert_eq!(mouse.update(&[&panel], at(10.0, 20.0)), vec![event(0, 0, 0, Hover)]);
+        assert_eq!(mouse.update(&[&panel], at(57.9, 67.9)), vec![event(0, 3, 2, Hover)]);
+        assert_eq!(mouse.update(&[&panel], at(57.9, 67.9)), vec![]);
+        assert_eq!(mouse.update(&[&panel], at(58.0, 67.9)), vec![event(0, 3, 2, Leave)]);
+        assert_eq!(mouse.update(&[&panel], at(57.9, 68.0)), vec![]);
+    }
+
+    #[test]
+    fn top_panel_wins_where_they_overlap()
+    {
+        let (big, small) = (big(), small());
+        assert_eq!(MouseRouter::new().update(&[&big, &small], at(50.0, 50.0)),
+                   vec![event(1, 1, 1, Hover)]);
+        assert_eq!(MouseRouter::new().update(&[&small, &big], at(50.0, 50.0)),
+                   vec![event(1, 3, 1, Hover)]);
+
+        // moving from the small panel onto the part of the big one it
+        // doesn't cover
+        let mut mouse = MouseRouter::new();
+        mouse.update(&[&big, &small], at(50.0, 50.0));
+        assert_eq!(mouse.update(&[&big, &small], at(30.0, 50.0)),
+                   vec![event(1, 1, 1, Leave), event(0, 1, 1, Hover)]);
+    }
+
+    #[test]
+    fn click_needs_release_on_the_same_cell()
+    {
+        let panel = big();
+        let mut mouse = MouseRouter::new();
+        mouse.update(&[&panel], at(15.0, 25.0));
+        assert_eq!(mouse.update(&[&panel], held(15.0, 25.0)), vec![event(0, 0, 0, Press(Left))]);
+        assert_eq!(mouse.update(&[&panel], at(20.0, 30.0)),
+                   vec![event(0, 0, 0, Release(Left)), event(0, 0, 0, Click(Left))]);
+
+        mouse.update(&[&panel], held(20.0, 30.0));
+        assert_eq!(mouse.update(&[&panel], held(30.0, 25.0)),
+                   vec![event(0, 1, 0, Hover), event(0, 1, 0, Drag(Left))]);
+        assert_eq!(mouse.update(&[&panel], held(31.0, 26.0)), vec![]);
+
+        // the drag follows the pointer off the panel, clamped to its edge
+        assert_eq!(mouse.update(&[&panel], held(100.0, 25.0)),
+                   vec![event(0, 1, 0, Leave), event(0, 3, 0, Drag(Left))]);
+        assert_eq!(mouse.update(&[&panel], at(100.0, 25.0)), vec![event(0, 3, 0, Release(Left))]);
+
+        // dragging away and back still counts as a click
+        mouse.update(&[&panel], held(15.0, 25.0));
+        mouse.update(&[&panel], held(30.0, 25.0));
+        let events = mouse.update(&[&panel], at(15.0, 25.0));
+        assert_eq!(events.last(), Some(&event(0, 0, 0, Click(Left))));
+
+        // pressing off every panel does nothing, even once over one
+        mouse.update(&[&panel], held(0.0, 0.0));
+        assert_eq!(mouse.update(&[&panel], at(15.0, 25.0)), vec![event(0, 0, 0, Hover)]);
+    }
+
+    #[test]
+    fn wheel_goes_to_the_panel_under_the_pointer()
+    {
+        let (big, small) = (big(), small());
+        let panels = [&big, &small];
+        let wheel = |x, y| MouseState { wheel: vec2(0.0, -1.0), ..at(x, y) };
+
+        let mut mouse = MouseRouter::new();
+        mouse.update(&panels, at(50.0, 50.0));
+        assert_eq!(mouse.update(&panels, wheel(50.0, 50.0)), vec![event(1, 1, 1, Wheel(0.0, -1.0))]);
+        mouse.update(&panels, at(15.0, 25.0));
+        assert_eq!(mouse.update(&panels, wheel(15.0, 25.0)), vec![event(0, 0, 0, Wheel(0.0, -1.0))]);
+        mouse.update(&panels, at(0.0, 0.0));
+        assert_eq!(mouse.update(&panels, wheel(0.0, 0.0)), vec![]);
+    }
+}
@@ -0,0 +1,331 @@
+//! Indexed colors.
+//!
+//! A cell's colors can be palette indices instead of fixed colors, looked
+//! up in the panel's palette when it's drawn. Swapping the palette
+//! recolors every indexed cell at once, and palette cycles rotate a range
+//! of entries over time for the classic waterfall and flicker effects
+//! without touching the cells.
+
+use macroquad::prelude::*;
+
+/// A color as stored in a cell: fixed, or an index into the panel's
+/// palette.
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub enum CellColor {
+    Rgb(Color),
+    Index(u8),
+}
+
+impl CellColor {
+    /// The color to draw. An index past the end of the palette has no
+    /// entry to look up and is drawn black.
+    pub fn resolve(self, palette: &Palette) -> Color
+    {
+        match self {
+            CellColor::Rgb(color) => color,
+            CellColor::Index(index) => palette.color(index).unwrap_or(BLACK),
+        }
+    }
+}
+
+impl From<Color> for CellColor {
+    fn from(color: Color) -> CellColor
+    {
+        CellColor::Rgb(color)
+    }
+}
+
+impl From<u8> for CellColor {
+    fn from(index: u8) -> CellColor
+    {
+        CellColor::Index(index)
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Palette {
+    pub colors: Vec<Color>,
+}
+
+impl Palette {
+    pub fn new(colors: Vec<Color>) -> Palette
+    {
+        Palette { colors }
+    }
+
+    /// A palette from `0xRRGGBB` values.
+    pub fn from_rgb(colors: &[u32]) -> Palette
+    {
+        Palette { colors: colors.iter().map(|&rgb| Color::from_hex(rgb)).collect() }
+    }
+
+    /// Entry `index`, or `None` past the end of the palette.
+    pub fn color(&self, index: u8) -> Option<Color>
+    {
+        self.colors.get(index as usize).copied()
+    }
+
+    /// Rotates entries `first` through `last`, inclusive, by `steps`
+    /// places towards `last`, wrapping around within the range. A range
+    /// running past the end of the palette stops at its last entry.
+    ///
+    /// Returns `false`, leaving the palette alone, if `first` is after
+    /// `last` or past the end of the palette.
+    pub fn rotate(&mut self, first: u8, last: u8, steps: i64) -> bool
+    {
+        let (first, last) = (first as usize, last as usize);
+        if first > last || first >= self.colors.len() {
+            return false;
+        }
+        let last = last.min(self.colors.len() - 1);
+        let steps = steps.rem_euclid((last - first + 1) as i64) as usize;
+        self.colors[first..=last].rotate_right(steps);
+        true
+    }
+
+    /// The 16 colors of the Apple II's low resolution graphics.
+    pub fn apple2_lores() -> Palette
+    {
+        Palette::from_rgb(&[
+            0x000000, 0xe31e60, 0x604ebd, 0xff44fd, 0x00a360, 0x9c9c9c, 0x14cffd, 0xd0c3ff,
+            0x607203, 0xff6a3c, 0x9c9c9c, 0xffa0d0, 0x14f53c, 0xd0dd8d, 0x72ffd0, 0xffffff,
+        ])
+    }
+
+    /// The 16 RGBI colors of the IBM CGA, with dark yellow shown as brown.
+    pub fn cga() -> Palette
+    {
+        Palette::from_rgb(&[
+            0x000000, 0x0000aa, 0x00aa00, 0x00aaaa, 0xaa0000, 0xaa00aa, 0xaa5500, 0xaaaaaa,
+            0x555555, 0x5555ff, 0x55ff55, 0x55ffff, 0xff5555, 0xff55ff, 0xffff55, 0xffffff,
+        ])
+    }
+
+    /// All 64 colors of the IBM EGA, in hardware order: the index bits are
+    /// `rgbRGB`, lowercase for the low intensity ones.
+    pub fn ega() -> Palette
+    {
+        let channel = |index: u32, bit: u32| {
+            (index >> bit & 1) * 0xaa + (index >> (bit + 3) & 1) * 0x55
+        };
+        let colors: Vec<u32> = (0..64)
+            .map(|i| channel(i, 2) << 16 | channel(i, 1) << 8 | channel(i, 0))
+            .collect();
+        Palette::from_rgb(&colors)
+    }
+
+    /// The Commodore 64's 16 colors, as measured by Pepto.
+    pub fn c64() -> Palette
+    {
+        Palette::from_rgb(&[
+            0x000000, 0xffffff, 0x68372b, 0x70a4b2, 0x6f3d86, 0x588d43, 0x352879, 0xb8c76f,
+            0x6f4f25, 0x433900, 0x9a6759, 0x444444, 0x6c6c6c, 0x9ad284, 0x6c5eb5, 0x959595,
+        ])
+    }
+
+    /// The ZX Spectrum's eight colors, then their BRIGHT versions.
+    pub fn zx_spectrum() -> Palette
+    {
+        Palette::from_rgb(&[
+            0x000000, 0x0000d7, 0xd70000, 0xd700d7, 0x00d700, 0x00d7d7, 0xd7d700, 0xd7d7d7,
+            0x000000, 0x0000ff, 0xff0000, 0xff00ff, 0x00ff00, 0x00ffff, 0xffff00, 0xffffff,
+        ])
+    }
+
+    /// The 64 entries of the NES's PPU palette.
+    pub fn nes() -> Palette
+    {
+        Palette::from_rgb(&[
+            0x7c7c7c, 0x0000fc, 0x0000bc, 0x4428bc, 0x940084, 0xa80020, 0xa81000, 0x881400,
+            0x503000, 0x007800, 0x006800, 0x005800, 0x004058, 0x000000, 0x000000, 0x000000,
+            0xbcbcbc, 0x0078f8, 0x0058f8, 0x6844fc, 0xd800cc, 0xe40058, 0xf83800, 0xe45c10,
+            0xac7c00, 0x00b800, 0x00a800, 0x00a844, 0x008888, 0x000000, 0x000000, 0x000000,
+            0xf8f8f8, 0x3cbcfc, 0x6888fc, 0x9878f8, 0xf878f8, 0xf85898, 0xf87858, 0xfca044,
+            0xf8b800, 0xb8f818, 0x58d854, 0x58f898, 0x00e8d8, 0x787878, 0x000000, 0x000000,
+            0xfcfcfc, 0xa4e4fc, 0xb8b8f8, 0xd8b8f8, 0xf8b8f8, 0xf8a4c0, 0xf0d0b0, 0xfce0a8,
+            0xf8d878, 0xd8f878, 0xb8f8b8, 0xb8f8d8, 0x00fcfc, 0xf8d8f8, 0x000000, 0x000000,
+        ])
+    }
+
+    /// The VGA's default 256 color palette: the 16 CGA colors, 16 grays,
+    /// then 24 hue ramps at three saturations and three brightnesses, and
+    /// 8 blacks.
+    pub fn vga() -> Palette
+    {
+        let mut levels: Vec<[u8; 3]> = vec![];
+        let grays = [0, 5, 8, 11, 14, 17, 20, 24, 28, 32, 36, 40, 45, 50, 56, 63];
+        levels.extend(grays.map(|g| [g, g, g]));
+
+        // Each ramp steps from one channel level to the next around the
+        // color wheel: blue, magenta, red, yellow, green, cyan.
+        let ramps: [[u8; 5]; 9] = [
+            [0, 16, 31, 47, 63], [31, 39, 47, 55, 63], [45, 49, 54, 58, 63],
+            [0, 7, 14, 21, 28], [14, 17, 21, 24, 28], [20, 22, 24, 26, 28],
+            [0, 4, 8, 12, 16], [8, 10, 12, 14, 16], [11, 12, 13, 15, 16],
+        ];
+        for v in ramps {
+            let (lo, hi) = (v[0], v[4]);
+            let up = [v[0], v[1], v[2], v[3]];
+            let down = [v[4], v[3], v[2], v[1]];
+            levels.extend(up.map(|c| [c, lo, hi]));
+            levels.extend(down.map(|c| [hi, lo, c]));
+            levels.extend(up.map(|c| [hi, c, lo]));
+            levels.extend(down.map(|c| [c, hi, lo]));
+            levels.extend(up.map(|c| [lo, hi, c]));
+            levels.extend(down.map(|c| [lo, c, hi]));
+        }
+        levels.extend([[0, 0, 0]; 8]);
+
+        // The DAC has six bits per channel.
+        let widen = |level: u8| (level << 2 | level >> 4) as u32;
+        let mut colors = Palette::cga().colors;
+        colors.extend(levels.iter().map(|&[r, g, b]| {
+            Color::from_hex(widen(r) << 16 | widen(g) << 8 | widen(b))
+        }));
+        Palette { colors }
+    }
+
+    /// The built-in palettes by name, e.g. for a settings menu.
+    pub fn presets() -> [(&'static str, Palette); 7]
+    {
+        [
+            ("Apple II", Palette::apple2_lores()),
+            ("CGA", Palette::cga()),
+            ("EGA", Palette::ega()),
+            ("C64", Palette::c64()),
+            ("ZX Spectrum", Palette::zx_spectrum()),
+            ("NES", Palette::nes()),
+            ("VGA", Palette::vga()),
+        ]
+    }
+}
+
+impl Default for Palette {
+    fn default() -> Palette
+    {
+        Palette::apple2_lores()
+    }
+}
+
+/// Rotates palette entries `first` through `last` one place every `step`
+/// seconds, towards `last`, or towards `first` if `reverse` is set.
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub struct PaletteCycle {
+    pub first: u8,
+    pub last: u8,
+    pub step: f64,
+    pub reverse: bool,
+}
+
+impl PaletteCycle {
+    pub fn new(first: u8, last: u8, step: f64) -> PaletteCycle
+    {
+        PaletteCycle { first, last, step, reverse: false }
+    }
+
+    /// How many places the entries have rotated after `time` seconds,
+    /// negative if reversed.
+    pub fn steps(&self, time: f64) -> i64
+    {
+        if self.step <= 0.0 {
+            return 0;
+        }
+        let steps = (time / self.step) as i64;
+        if self.reverse { -steps } else { steps }
+    }
+
+    /// Applies the rotation for `time` seconds on the clock to `palette`.
+    /// Returns `false` if the range doesn't fit it, as `Palette::rotate`
+    /// does.
+    pub fn apply(&self, palette: &mut Palette, time: f64) -> bool
+    {
+        palette.rotate(self.first, self.last, self.steps(time))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn hex(palette: &Palette, index: u8) -> u32
+    {
+        let [r, g, b, _]: [u8; 4] = palette.color(index).unwrap().into();
+        (r as u32) << 16 | (g as u32) << 8 | b as u32
+    }
+
+    #[test]
+    fn vga_palette()
+    {
+        let vga = Palette::vga();
+        assert_eq!(vga.colors.len(), 256);
+        assert_eq!(vga.colors[..16], Palette::cga().colors[..]);
+        let known = [
+            (16, 0x000000), (31, 0xffffff), (32, 0x0000ff), (40, 0xff0000), (55, 0x0041ff),
+            (56, 0x7d7dff), (104, 0x000071), (176, 0x000041), (247, 0x2c3041), (248, 0x000000),
+        ];
+        for (index, rgb) in known {
+            assert_eq!(hex(&vga, index), rgb, "entry {}", index);
+        }
+    }
+
+    #[test]
+    fn ega_palette()
+    {
+        let ega = Palette::ega();
+        assert_eq!(ega.colors.len(), 64);
+        for (index, rgb) in [(0, 0x000000), (7, 0xaaaaaa), (20, 0xaa5500), (56, 0x555555), (63, 0xffffff)] {
+            assert_eq!(hex(&ega, index), rgb, "entry {}", index);
+        }
+    }
+
+    #[test]
+    fn out_of_range_entries()
+    {
+        let palette = Palette::from_rgb(&[0xff0000, 0x00ff00]);
+        assert_eq!(hex(&palette, 1), 0x00ff00);
+        assert_eq!(palette.color(2), None);
+        assert_eq!(CellColor::Index(2).resolve(&palette), BLACK);
+        assert_eq!(Palette::new(vec![]).color(0), None);
+    }
+
+    #[test]
+    fn rotation()
+    {
+        let mut palette = Palette::from_rgb(&[0, 1, 2, 3, 4]);
+        assert!(palette.rotate(1, 3, 1));
+        assert_eq!(palette, Palette::from_rgb(&[0, 3, 1, 2, 4]));
+        assert!(palette.rotate(1, 3, -4));
+        assert_eq!(palette, Palette::from_rgb(&[0, 1, 2, 3, 4]));
+
+        // a range past the end stops at the last entry
+        assert!(palette.rotate(3, 200, 1));
+        assert_eq!(palette, Palette::from_rgb(&[0, 1, 2, 4, 3]));
+
+        assert!(palette.rotate(2, 2, 7));
+        assert!(!palette.rotate(3, 1, 1));
+        assert!(!palette.rotate(5, 9, 1));
+        assert_eq!(palette, Palette::from_rgb(&[0, 1, 2, 4, 3]));
+    }
+
+    #[test]
+    fn cycle_steps()
+    {
+        let mut cycle = PaletteCycle::new(1, 3, 0.5);
+        assert_eq!([0.0, 0.49, 0.5, 1.2].map(|t| cycle.steps(t)), [0, 0, 1, 2]);
+        cycle.reverse = true;
+        assert_eq!(cycle.steps(1.2), -2);
+        assert_eq!(PaletteCycle::new(1, 3, 0.0).steps(5.0), 0);
+
+        let source = Palette::apple2_lores();
+        let mut palette = source.clone();
+        assert!(PaletteCycle::new(1, 3, 0.5).apply(&mut palette, 1.2));
+        assert_eq!(palette.color(1), source.color(2));
+        assert_eq!(palette.color(2), source.color(3));
+        assert_eq!(palette.color(3), source.color(1));
+        assert_eq!(palette.color(4), source.color(4));
+
+        let mut palette = source.clone();
+        assert!(cycle.apply(&mut palette, 1.2));
+        assert_eq!(palette.color(1), source.color(3));
+    }
+}
@@ -1,15 +1,137 @@
+use std::cell::RefCell;
+use std::fmt;
+use std::io;
+
 use macroquad::prelude::*;
 use crate::font::BdgFont;
+use crate::cache::RenderCache;
+pub use crate::cursor::{CursorShape, CursorStyle};
+pub use crate::palette::{CellColor, Palette, PaletteCycle};
+pub use crate::surface::{TextSurface, Transparency};
+
+pub mod boxes;
+
+/// Seconds that flashing cells spend in each state, close to the rate of
+/// the Apple II's FLASH text.
+pub const FLASH_HALF_PERIOD: f64 = 0.25;
+
+/// Display attributes of a cell, as a set of bit flags.
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Default)]
+pub struct Attrs(pub u8);
+
+impl Attrs {
+    pub const NONE: Attrs = Attrs(0);
+    /// Foreground and background swapped.
+    pub const INVERSE: Attrs = Attrs(0x01);
+    /// Alternates between normal and inverse on the shared flash clock.
+    pub const FLASH: Attrs = Attrs(0x02);
+    pub const UNDERLINE: Attrs = Attrs(0x04);
+    /// Foreground drawn at half brightness.
+    pub const DIM: Attrs = Attrs(0x08);
+
+    pub fn contains(self, other: Attrs) -> bool
+    {
+        self.0 & other.0 == other.0
+    }
+
+    pub fn insert(&mut self, other: Attrs)
+    {
+        self.0 |= other.0;
+    }
+
+    pub fn remove(&mut self, other: Attrs)
+    {
+        self.0 &= !other.0;
+    }
+}
+
+impl std::ops::BitOr for Attrs {
+    type Output = Attrs;
+
+    fn bitor(self, other: Attrs) -> Attrs
+    {
+        Attrs(self.0 | other.0)
+    }
+}
 
-pub struct Panel<'a> {
+/// One character cell: the character, the colors to draw it with and its
+/// attributes. A cell with no background shows the panel's erase color,
+/// if any.
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub struct Cell {
+    pub ch: char,
+    pub fg: CellColor,
+    pub bg: Option<CellColor>,
+    pub attrs: Attrs,
+}
+
+impl Cell {
+    pub fn new(ch: char, fg: Color, bg: Option<Color>) -> Cell
+    {
+        Cell { ch, fg: fg.into(), bg: bg.map(CellColor::from), attrs: Attrs::NONE }
+    }
+
+    /// A cell colored by entries of the panel's palette.
+    pub fn indexed(ch: char, fg: u8, bg: Option<u8>) -> Cell
+    {
+        Cell { ch, fg: fg.into(), bg: bg.map(CellColor::from), attrs: Attrs::NONE }
+    }
+
+    pub fn with_attrs(ch: char, fg: CellColor, bg: Option<CellColor>, attrs: Attrs) -> Cell
+    {
+        Cell { ch, fg, bg, attrs }
+    }
+}
+
+/// True during the "on" half of the shared flash clock. All panels use
+/// the same clock so flashing text stays in step across the screen.
+pub fn flash_phase() -> bool
+{
+    (get_time() / FLASH_HALF_PERIOD) as u64 % 2 == 1
+}
+
+/// The foreground and background a cell is actually drawn with once its
+/// palette indices are looked up in `palette` and its attributes are
+/// applied. `erase_color` is the panel's, used as the foreground of
+/// inverse cells that have no background; `flash_on` is the current
+/// `flash_phase()`.
+pub fn resolve_cell_colors(cell: &Cell, palette: &Palette, erase_color: Option<Color>,
+                           flash_on: bool) -> (Color, Option<Color>)
+{
+    let mut fg = cell.fg.resolve(palette);
+    let bg = cell.bg.map(|bg| bg.resolve(palette));
+    if cell.attrs.contains(Attrs::DIM) {
+        fg = Color::new(fg.r * 0.5, fg.g * 0.5, fg.b * 0.5, fg.a);
+    }
+
+    let flashing = cell.attrs.contains(Attrs::FLASH) && flash_on;
+    if cell.attrs.contains(Attrs::INVERSE) != flashing {
+        let back = bg.or(erase_color).unwrap_or(BLACK);
+        return (back, Some(fg));
+    }
+    (fg, bg)
+}
+
+pub struct Panel {
     pub screen_x: f32,
     pub screen_y: f32,
-    pub font_color: Color,
+
+    /// Colors and attributes given to cells written by `put_char` and
+    /// `write_string`.
+    pub font_color: CellColor,
+    pub back_color: Option<CellColor>,
+    pub attrs: Attrs,
+
     pub erase_color: Option<Color>,
-    pub font: BdgFont<'a>,
+
+    /// What indexed cell colors are looked up in, and the cycles that
+    /// rotate its entries as time passes.
+    pub palette: Palette,
+    pub palette_cycles: Vec<PaletteCycle>,
+    pub font: BdgFont,
     pub char_width: u32,
     pub char_height: u32,
-    pub chars: Vec<Vec<char>>,
+    pub cells: Vec<Vec<Cell>>,
 
     pub pixel_scale: u32,
 
@@ -18,111 +140,844 @@ pub struct Panel<'a> {
 
     pub cursor_x: u32,
     pub cursor_y: u32,
-}
+    pub cursor_style: CursorStyle,
 
+    /// Whether writing past the right edge continues on the next line.
+    /// When off, further characters overwrite the last column.
+    pub wrap: bool,
 
-fn draw_char(c: char, color: Color, x: f32, y: f32, scale: u32, font: &BdgFont)
-{
-    let char_ascii = c as u32;
-
-    let cx = char_ascii % 16;
-    let cy = (char_ascii / 16) - 2;
-    
-    let tx = (font.width as u32 * cx) as f32;
-    let ty = (font.height as u32 * cy) as f32;
-
-    draw_texture_ex(
-        &font.texture,
-        x, y,
-        color,
-        DrawTextureParams {
-            source: Some(Rect{x: tx, y: ty,
-                              w: font.width as f32,
-                              h: font.height as f32}),
-            dest_size: Some(Vec2{
-                x: (font.width * scale) as f32,
-                y: (font.height * scale) as f32}),
-            ..Default::default()
-        }
-    );
-}
-
-        
-
-pub fn make_panel(sx: f32, sy: f32,
-                  font_color: Color,
-                  erase_color: Option<Color>,
-                  scale: u32,
-                  font: BdgFont, w: u32, h: u32) -> Panel
-{
-    let char_row = vec![' '; w as usize];
-    let mut char_vec = vec![];
-    for _i in 0..h
-    {
-        char_vec.push(char_row.clone());
-    }
-    
-    let p = Panel{screen_x: sx,
-                  screen_y: sy,
-                  font_color: font_color,
-                  erase_color: erase_color,
-                  pixel_scale: scale,
-                  font: font,
-                  char_width: w,
-                  char_height: h,
-                  chars: char_vec,
-                  screen_width: (w * font.width as u32 * scale) as f32,
-                  screen_height: (w * font.height as u32 * scale) as f32,
-
-                  cursor_x: 0,
-                  cursor_y: 0,
-    };
-    
-    println!("I made a panel");
-    return p;
-}
-
-pub fn draw_panel(panel: &Panel)
+    /// First and last rows, inclusive, that scroll when the cursor moves
+    /// down past `scroll_bottom`.
+    pub scroll_top: u32,
+    pub scroll_bottom: u32,
+
+    /// Columns that TAB advances to, in increasing order.
+    pub tab_stops: Vec<u32>,
+
+    /// Whether LF also returns the cursor to the left edge, as on a
+    /// teletype. Turn this off for output that sends CR LF itself.
+    pub lf_returns: bool,
+
+    /// Called when BEL is written, e.g. to play a beep.
+    pub bell: Option<Box<dyn FnMut()>>,
+
+    /// The start of a UTF-8 character cut off at the end of an
+    /// `io::Write::write` call.
+    utf8_pending: Vec<u8>,
+
+    render_cache: RefCell<RenderCache>,
+}
+
+/// Tab stops every eight columns across a panel `width` cells wide.
+pub fn default_tab_stops(width: u32) -> Vec<u32>
 {
-    if panel.erase_color.is_some()
+    (1..width.div_ceil(8)).map(|i| i * 8).collect()
+}
+
+
+/// Settings for a new panel. Only the font and size are required, e.g.
+/// `Panel::builder(font, 40, 24).position(100.0, 100.0).scale(2).build()`.
+pub struct PanelBuilder {
+    font: BdgFont,
+    width: u32,
+    height: u32,
+    screen_x: f32,
+    screen_y: f32,
+    font_color: Color,
+    erase_color: Option<Color>,
+    pixel_scale: u32,
+    palette: Palette,
+    cursor_style: CursorStyle,
+}
+
+impl PanelBuilder {
+    /// A `width` by `height` cell panel at the top left of the screen,
+    /// white on black and drawn at scale 1. Panels with an opaque erase
+    /// color are cached in a render target and only redraw the cells that
+    /// change; see `erase_color` for the cost of other colors.
+    pub fn new(font: BdgFont, width: u32, height: u32) -> PanelBuilder
+    {
+        PanelBuilder {
+            font,
+            width,
+            height,
+            screen_x: 0.0,
+            screen_y: 0.0,
+            font_color: WHITE,
+            erase_color: Some(BLACK),
+            pixel_scale: 1,
+            palette: Palette::default(),
+            cursor_style: CursorStyle::default(),
+        }
+    }
+
+    pub fn position(mut self, x: f32, y: f32) -> PanelBuilder
+    {
+        self.screen_x = x;
+        self.screen_y = y;
+        self
+    }
+
+    pub fn font_color(mut self, color: Color) -> PanelBuilder
+    {
+        self.font_color = color;
+        self
+    }
+
+    /// The color drawn behind the whole panel, or `None` to leave what's
+    /// underneath showing through. Panels without an opaque erase color
+    /// rebuild their meshes whenever anything changes and draw every cell
+    /// each frame.
+    pub fn erase_color(mut self, color: Option<Color>) -> PanelBuilder
+    {
+        self.erase_color = color;
+        self
+    }
+
+    /// Screen pixels per font pixel.
+    pub fn scale(mut self, scale: u32) -> PanelBuilder
+    {
+        self.pixel_scale = scale;
+        self
+    }
+
+    /// The palette indexed colors are looked up in. The default is the
+    /// Apple II's 16 lo-res colors.
+    pub fn palette(mut self, palette: Palette) -> PanelBuilder
+    {
+        self.palette = palette;
+        self
+    }
+
+    pub fn cursor(mut self, style: CursorStyle) -> PanelBuilder
+    {
+        self.cursor_style = style;
+        self
+    }
+
+    pub fn build(self) -> Panel
+    {
+        let w = self.width;
+        let h = self.height;
+        let scale = self.pixel_scale;
+        let font = self.font;
+
+        Panel{screen_x: self.screen_x,
+              screen_y: self.screen_y,
+              font_color: self.font_color.into(),
+              back_color: None,
+              attrs: Attrs::NONE,
+              erase_color: self.erase_color,
+              palette: self.palette,
+              palette_cycles: vec![],
+              pixel_scale: scale,
+              screen_width: (w * font.width * scale) as f32,
+              screen_height: (h * font.height * scale) as f32,
+              font,
+              char_width: w,
+              char_height: h,
+              cells: vec![vec![Cell::new(' ', self.font_color, None); w as usize]; h as usize],
+
+              cursor_x: 0,
+              cursor_y: 0,
+              cursor_style: self.cursor_style,
+
+              wrap: true,
+              scroll_top: 0,
+              scroll_bottom: h.saturating_sub(1),
+
+              tab_stops: default_tab_stops(w),
+              lf_returns: true,
+              bell: None,
+              utf8_pending: vec![],
+              render_cache: RefCell::default(),
+        }
+    }
+}
+
+impl Panel {
+    pub fn builder(font: BdgFont, width: u32, height: u32) -> PanelBuilder
+    {
+        PanelBuilder::new(font, width, height)
+    }
+
+    /// Draws the panel. It's kept in a render target between frames, and
+    /// only cells that changed since the last draw are drawn again.
+    pub fn draw(&self)
+    {
+        self.render_cache.borrow_mut().draw(self, flash_phase(), get_time());
+    }
+
+    /// Whether drawing the panel now would change the screen: cells were
+    /// written, flashing cells are due to flash, the cursor is due to
+    /// blink, the panel moved or was invalidated. When no panel has
+    /// changed the frame can be skipped.
+    pub fn changed(&self) -> bool
+    {
+        self.render_cache.borrow_mut().changed(self, flash_phase(), get_time())
+    }
+
+    /// Whether the cursor is showing right now, rather than hidden or in
+    /// the off half of its blink.
+    pub fn cursor_on(&self) -> bool
+    {
+        self.render_cache.borrow().cursor_on(self, get_time())
+    }
+
+    /// The cells that will be drawn again by the next `draw`, or every
+    /// cell if the panel will be redrawn in full.
+    pub fn dirty_cells(&self) -> Vec<(u32, u32)>
+    {
+        let mut cache = self.render_cache.borrow_mut();
+        cache.resolve_palette(self, get_time());
+        cache.dirty_cells(self, flash_phase())
+            .unwrap_or_else(|| {
+                (0..self.char_height)
+                    .flat_map(|y| (0..self.char_width).map(move |x| (x, y)))
+                    .collect()
+            })
+    }
+
+    /// Makes the next `draw` redraw the whole panel, e.g. after changing
+    /// an atlas the panel's font draws from.
+    pub fn invalidate(&mut self)
+    {
+        self.render_cache.get_mut().invalidate();
+    }
+
+    /// Makes the next `draw` redraw the `w` by `h` cells at `x`, `y`.
+    pub fn invalidate_rect(&mut self, x: u32, y: u32, w: u32, h: u32)
+    {
+        self.render_cache.get_mut().invalidate_rect(x, y, w, h);
+    }
+
+    /// The cell under the screen position `pos`, e.g. the mouse pointer,
+    /// or `None` if it's off the panel.
+    pub fn cell_at(&self, pos: Vec2) -> Option<(u32, u32)>
+    {
+        let cell_w = (self.font.width * self.pixel_scale) as f32;
+        let cell_h = (self.font.height * self.pixel_scale) as f32;
+        let x = (pos.x - self.screen_x) / cell_w;
+        let y = (pos.y - self.screen_y) / cell_h;
+        if x < 0.0 || y < 0.0 || x.is_nan() || y.is_nan()
+        {
+            return None;
+        }
+        let (x, y) = (x as u32, y as u32);
+        (x < self.char_width && y < self.char_height).then_some((x, y))
+    }
+
+    /// Where the cell at `x`, `y` is drawn on screen.
+    pub fn cell_rect(&self, x: u32, y: u32) -> Rect
+    {
+        let cell_w = (self.font.width * self.pixel_scale) as f32;
+        let cell_h = (self.font.height * self.pixel_scale) as f32;
+        Rect::new(self.screen_x + x as f32 * cell_w, self.screen_y + y as f32 * cell_h, cell_w, cell_h)
+    }
+
+    /// Switches the panel to another font and resizes it on screen to match.
+    pub fn set_font(&mut self, font: BdgFont)
+    {
+        self.screen_width = (self.char_width * font.width * self.pixel_scale) as f32;
+        self.screen_height = (self.char_height * font.height * self.pixel_scale) as f32;
+        self.font = font;
+    }
+
+    /// Changes the panel to `w` by `h` cells. Cells that still fit keep their
+    /// contents, new ones are blank, and the scroll region and tab stops are
+    /// reset to cover the new size.
+    pub fn resize(&mut self, w: u32, h: u32)
+    {
+        let blank = self.blank_cell();
+        self.cells.resize(h as usize, vec![blank; w as usize]);
+        for row in &mut self.cells
+        {
+            row.resize(w as usize, blank);
+        }
+
+        self.char_width = w;
+        self.char_height = h;
+        self.screen_width = (w * self.font.width * self.pixel_scale) as f32;
+        self.screen_height = (h * self.font.height * self.pixel_scale) as f32;
+
+        self.cursor_x = self.cursor_x.min(w);
+        self.cursor_y = self.cursor_y.min(h.saturating_sub(1));
+        self.scroll_top = 0;
+        self.scroll_bottom = h.saturating_sub(1);
+        self.tab_stops = default_tab_stops(w);
+    }
+
+    /// Sets the colors used for cells written from now on.
+    pub fn set_colors(&mut self, fg: Color, bg: Option<Color>)
+    {
+        self.font_color = fg.into();
+        self.back_color = bg.map(CellColor::from);
+    }
+
+    /// Sets palette entries as the colors used for cells written from now
+    /// on, so they change along with the palette.
+    pub fn set_palette_colors(&mut self, fg: u8, bg: Option<u8>)
+    {
+        self.font_color = fg.into();
+        self.back_color = bg.map(CellColor::from);
+    }
+
+    /// Swaps the palette, recoloring every cell that uses indexed colors.
+    pub fn set_palette(&mut self, palette: Palette)
+    {
+        self.palette = palette;
+    }
+
+    /// Starts rotating part of the palette. Returns `false` and ignores
+    /// the cycle if its `first` entry is after its `last`.
+    pub fn add_palette_cycle(&mut self, cycle: PaletteCycle) -> bool
+    {
+        if cycle.first > cycle.last
+        {
+            return false;
+        }
+        self.palette_cycles.push(cycle);
+        true
+    }
+
+    pub fn clear_palette_cycles(&mut self)
+    {
+        self.palette_cycles.clear();
+    }
+
+    /// The palette as it looks `time` seconds into the clock, with the
+    /// palette cycles applied.
+    pub fn palette_at(&self, time: f64) -> Palette
+    {
+        let mut palette = self.palette.clone();
+        for cycle in &self.palette_cycles
+        {
+            cycle.apply(&mut palette, time);
+        }
+        palette
+    }
+
+    /// Sets the attributes used for cells written from now on.
+    pub fn set_attrs(&mut self, attrs: Attrs)
+    {
+        self.attrs = attrs;
+    }
+
+    pub fn write_string(&mut self, s: &str)
+    {
+        self.write_string_attrs(s, self.attrs);
+    }
+
+    /// Writes `s` at the cursor with `attrs` instead of the current
+    /// attributes, e.g. `panel.write_string_attrs("PRESS START",
+    /// Attrs::FLASH)`.
+    pub fn write_string_attrs(&mut self, s: &str, attrs: Attrs)
+    {
+        for c in s.chars()
+        {
+            match c
+            {
+                '\n' => {
+                    if self.lf_returns
+                    {
+                        self.cursor_x = 0;
+                    }
+                    self.line_feed();
+                }
+                '\r' => self.cursor_x = 0,
+                '\t' => self.tab(),
+                '\x08' => {
+                    self.cursor_x = self.cursor_x.min(self.char_width).saturating_sub(1);
+                }
+                '\x0c' => {
+                    self.clear();
+                    self.set_cursor_pos(0, 0);
+                }
+                '\x07' => {
+                    if let Some(bell) = self.bell.as_mut()
+                    {
+                        bell();
+                    }
+                }
+                c if c.is_control() => {}
+                c => {
+                    let cell = Cell::with_attrs(c, self.font_color, self.back_color, attrs);
+                    self.write_cell(cell);
+                }
+            }
+        }
+    }
+
+    /// Moves the cursor to the next tab stop, or the last column if there
+    /// are no more stops on this line.
+    fn tab(&mut self)
+    {
+        let last = self.char_width.saturating_sub(1);
+        self.cursor_x = self.tab_stops.iter()
+            .copied()
+            .find(|&stop| stop > self.cursor_x)
+            .unwrap_or(last)
+            .min(last);
+    }
+
+    /// Sets tab stops every `width` columns.
+    pub fn set_tab_width(&mut self, width: u32)
+    {
+        let width = width.max(1);
+        self.tab_stops = (1..self.char_width.div_ceil(width)).map(|i| i * width).collect();
+    }
+
+    pub fn set_tab_stops(&mut self, stops: &[u32])
+    {
+        self.tab_stops = stops.to_vec();
+        self.tab_stops.sort_unstable();
+        self.tab_stops.dedup();
+    }
+
+    /// Sets the function called when BEL is written.
+    pub fn set_bell(&mut self, bell: Box<dyn FnMut()>)
+    {
+        self.bell = Some(bell);
+    }
+
+    /// Writes one cell at the cursor and advances it, wrapping and scrolling
+    /// as needed. The wrap is deferred until the next write, so filling the
+    /// bottom right cell doesn't scroll the panel.
+    fn write_cell(&mut self, cell: Cell)
+    {
+        if self.char_width == 0 || self.char_height == 0
+        {
+            return;
+        }
+
+        if self.cursor_x >= self.char_width
+        {
+            if self.wrap
+            {
+                self.cursor_x = 0;
+                self.line_feed();
+            }
+            else
+            {
+                self.cursor_x = self.char_width - 1;
+            }
+        }
+        self.cursor_y = self.cursor_y.min(self.char_height - 1);
+
+        self.cells[self.cursor_y as usize][self.cursor_x as usize] = cell;
+        self.cursor_x += 1;
+    }
+
+    /// Moves the cursor down a row, scrolling the scroll region up when the
+    /// cursor is on its bottom row.
+    pub fn line_feed(&mut self)
+    {
+        if self.cursor_y == self.scroll_bottom
+        {
+            self.scroll_up(1);
+        }
+        else if self.cursor_y + 1 < self.char_height
+        {
+            self.cursor_y += 1;
+        }
+    }
+
+    /// Scrolls the rows of the scroll region up by `n`, filling the rows
+    /// uncovered at the bottom with blanks in the current colors.
+    pub fn scroll_up(&mut self, n: u32)
     {
-        draw_rectangle(panel.screen_x,
-                       panel.screen_y,
-                       panel.screen_width,
-                       panel.screen_height,
-                       panel.erase_color.unwrap());
+        let top = self.scroll_top as usize;
+        let bottom = self.scroll_bottom as usize;
+        if top > bottom || bottom >= self.cells.len()
+        {
+            return;
+        }
+        let n = (n as usize).min(bottom - top + 1);
+
+        self.cells[top..=bottom].rotate_left(n);
+        let blank = self.blank_cell();
+        for row in &mut self.cells[bottom + 1 - n..=bottom]
+        {
+            row.fill(blank);
+        }
     }
 
-    for x in 0..panel.char_width
+    /// Scrolls the rows of the scroll region down by `n`, filling the rows
+    /// uncovered at the top with blanks in the current colors.
+    pub fn scroll_down(&mut self, n: u32)
     {
-        for y in 0..panel.char_height
+        let top = self.scroll_top as usize;
+        let bottom = self.scroll_bottom as usize;
+        if top > bottom || bottom >= self.cells.len()
+        {
+            return;
+        }
+        let n = (n as usize).min(bottom - top + 1);
+
+        self.cells[top..=bottom].rotate_right(n);
+        let blank = self.blank_cell();
+        for row in &mut self.cells[top..top + n]
         {
-            let c = panel.chars[y as usize][x as usize];
-            draw_char(c, panel.font_color,
-                      panel.screen_x + (panel.font.width * x * panel.pixel_scale) as f32,
-                      panel.screen_y + (panel.font.height * y * panel.pixel_scale) as f32,
-                      panel.pixel_scale,
-                      &panel.font);
+            row.fill(blank);
         }
     }
+
+    pub fn set_cursor_style(&mut self, style: CursorStyle)
+    {
+        self.cursor_style = style;
+    }
+
+    pub fn show_cursor(&mut self, visible: bool)
+    {
+        self.cursor_style.visible = visible;
+    }
+
+    pub fn set_wrap(&mut self, wrap: bool)
+    {
+        self.wrap = wrap;
+    }
+
+    /// Limits scrolling to rows `top` through `bottom`, inclusive, e.g. to
+    /// keep a status line fixed. Out-of-range regions reset to the whole
+    /// panel.
+    pub fn set_scroll_region(&mut self, top: u32, bottom: u32)
+    {
+        if top < bottom && bottom < self.char_height
+        {
+            self.scroll_top = top;
+            self.scroll_bottom = bottom;
+        }
+        else
+        {
+            self.scroll_top = 0;
+            self.scroll_bottom = self.char_height.saturating_sub(1);
+        }
+    }
+
+    /// Puts `c` at `x`, `y` in the current colors and attributes without
+    /// moving the cursor.
+    pub fn put_char(&mut self, c: char, x: u32, y: u32)
+    {
+        let cell = Cell::with_attrs(c, self.font_color, self.back_color, self.attrs);
+        self.put_cell(cell, x, y);
+    }
+
+    /// Fills the `w` by `h` rectangle at `x`, `y` with `c` in the current
+    /// colors and attributes.
+    pub fn fill_char(&mut self, c: char, x: u32, y: u32, w: u32, h: u32)
+    {
+        let cell = Cell::with_attrs(c, self.font_color, self.back_color, self.attrs);
+        self.fill(cell, x, y, w, h);
+    }
 }
 
-pub fn panel_set_cursor_pos(panel: &mut Panel, x: u32, y: u32)
-{
-    panel.cursor_x = x;
-    panel.cursor_y = y;
+impl TextSurface for Panel {
+    fn size(&self) -> (u32, u32)
+    {
+        (self.char_width, self.char_height)
+    }
+
+    fn get_cell(&self, x: u32, y: u32) -> Option<Cell>
+    {
+        self.cells.get(y as usize)?.get(x as usize).copied()
+    }
+
+    fn put_cell(&mut self, cell: Cell, x: u32, y: u32)
+    {
+        if let Some(target) = self.cells.get_mut(y as usize).and_then(|row| row.get_mut(x as usize))
+        {
+            *target = cell;
+        }
+    }
+
+    fn cursor(&self) -> (u32, u32)
+    {
+        (self.cursor_x, self.cursor_y)
+    }
+
+    fn set_cursor_pos(&mut self, x: u32, y: u32)
+    {
+        self.cursor_x = x;
+        self.cursor_y = y;
+    }
+
+    fn blank_cell(&self) -> Cell
+    {
+        Cell::with_attrs(' ', self.font_color, self.back_color, Attrs::NONE)
+    }
+
+    /// Copies whole row slices, working away from the destination so
+    /// overlapping rows aren't overwritten before they're read.
+    fn copy_rect(&mut self, x: u32, y: u32, w: u32, h: u32, to_x: u32, to_y: u32)
+    {
+        let w = w.min(self.char_width.saturating_sub(x)).min(self.char_width.saturating_sub(to_x));
+        let h = h.min(self.char_height.saturating_sub(y)).min(self.char_height.saturating_sub(to_y));
+        if w == 0
+        {
+            return;
+        }
+        let (x, to_x, w) = (x as usize, to_x as usize, w as usize);
+
+        for i in 0..h
+        {
+            let i = if to_y > y { h - 1 - i } else { i };
+            let from = (y + i) as usize;
+            let to = (to_y + i) as usize;
+            if from == to
+            {
+                self.cells[from].copy_within(x..x + w, to_x);
+            }
+            else if from < to
+            {
+                let (above, below) = self.cells.split_at_mut(to);
+                below[0][to_x..to_x + w].copy_from_slice(&above[from][x..x + w]);
+            }
+            else
+            {
+                let (above, below) = self.cells.split_at_mut(from);
+                above[to][to_x..to_x + w].copy_from_slice(&below[0][x..x + w]);
+            }
+        }
+    }
+
+    fn clear(&mut self)
+    {
+        let blank = self.blank_cell();
+        for row in &mut self.cells
+        {
+            row.fill(blank);
+        }
+    }
 }
 
-pub fn panel_write_string(panel: &mut Panel, s: &str)
-{
-    for c in s.chars()
+/// `write!(panel, ...)` writes at the cursor, just like `write_string`.
+impl fmt::Write for Panel {
+    fn write_str(&mut self, s: &str) -> fmt::Result
     {
-        panel.chars[panel.cursor_y as usize][panel.cursor_x as usize] = c;
-        panel.cursor_x += 1;
+        self.write_string(s);
+        Ok(())
     }
 }
 
-pub fn panel_put_char(panel: &mut Panel, c: char, x: u32, y: u32)
-{
-    panel.chars[y as usize][x as usize] = c;
+/// Bytes are decoded as UTF-8 and written at the cursor. A character split
+/// across two writes is held back until the rest arrives; invalid bytes
+/// are written as U+FFFD.
+impl io::Write for Panel {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize>
+    {
+        let mut bytes = std::mem::take(&mut self.utf8_pending);
+        bytes.extend_from_slice(buf);
+
+        let mut rest = &bytes[..];
+        while !rest.is_empty()
+        {
+            match std::str::from_utf8(rest)
+            {
+                Ok(s) => {
+                    self.write_string(s);
+                    break;
+                }
+                Err(e) => {
+                    let (valid, after) = rest.split_at(e.valid_up_to());
+                    self.write_string(std::str::from_utf8(valid).unwrap_or_default());
+                    match e.error_len()
+                    {
+                        Some(bad) => {
+                            self.write_string("\u{fffd}");
+                            rest = &after[bad..];
+                        }
+                        None => {
+                            self.utf8_pending = after.to_vec();
+                            break;
+                        }
+                    }
+                }
+            }
+        }
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> io::Result<()>
+    {
+        Ok(())
+    }
+}
+
+/// Writes formatted text into a panel starting at cell `x`, `y`, e.g.
+/// `panel_print!(&mut panel, 1, 0, "Score: {:05}", score)`. The cursor is
+/// left after the text.
+#[macro_export]
+macro_rules! panel_print {
+    ($panel:expr, $x:expr, $y:expr, $($arg:tt)*) => {{
+        let panel: &mut $crate::panel::Panel = $panel;
+        $crate::surface::TextSurface::set_cursor_pos(panel, $x, $y);
+        let _ = ::std::fmt::Write::write_fmt(panel, format_args!($($arg)*));
+    }};
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::font::make_font;
+    use crate::font::tests::texture;
+
+    fn panel(w: u32, h: u32) -> Panel
+    {
+        Panel::builder(make_font(&texture(), 6, 8), w, h).font_color(WHITE).build()
+    }
+
+    /// The panel's text, one line per row with trailing blanks dropped.
+    fn text(panel: &Panel) -> String
+    {
+        let rows: Vec<String> = panel.cells.iter()
+            .map(|row| row.iter().map(|cell| cell.ch).collect::<String>().trim_end().to_string())
+            .collect();
+        rows.join("\n")
+    }
+
+    #[test]
+    fn writes_wrap_at_the_right_edge()
+    {
+        let mut p = panel(4, 3);
+        p.write_string("abcdefgh");
+        assert_eq!(text(&p), "abcd\nefgh\n");
+        // the cursor waits past the edge until the next character
+        assert_eq!((p.cursor_x, p.cursor_y), (4, 1));
+
+        p.write_string("i");
+        assert_eq!(text(&p), "abcd\nefgh\ni");
+
+        p.set_wrap(false);
+        p.write_string("jklmn");
+        assert_eq!(text(&p), "abcd\nefgh\nijkn");
+        assert_eq!((p.cursor_x, p.cursor_y), (4, 2));
+    }
+
+    #[test]
+    fn filling_the_last_row_scrolls_on_the_next_write()
+    {
+        let mut p = panel(2, 2);
+        p.write_string("abcd");
+        assert_eq!(text(&p), "ab\ncd");
+        p.write_string("e");
+        assert_eq!(text(&p), "cd\ne");
+    }
+
+    #[test]
+    fn scrolling_stays_inside_the_scroll_region()
+    {
+        let mut p = panel(4, 4);
+        for (y, word) in ["one", "two", "six", "ten"].iter().enumerate() {
+            p.set_cursor_pos(0, y as u32);
+            p.write_string(word);
+        }
+
+        p.set_scroll_region(1, 2);
+        p.set_cursor_pos(0, 2);
+        p.line_feed();
+        assert_eq!(text(&p), "one\nsix\n\nten");
+        assert_eq!(p.cursor_y, 2);
+
+        p.scroll_down(1);
+        assert_eq!(text(&p), "one\n\nsix\nten");
+        p.scroll_up(5);
+        assert_eq!(text(&p), "one\n\n\nten");
+
+        // a bad region resets to the whole panel
+        p.set_scroll_region(3, 1);
+        assert_eq!((p.scroll_top, p.scroll_bottom), (0, 3));
+        p.scroll_up(1);
+        assert_eq!(text(&p), "\n\nten\n");
+    }
+
+    #[test]
+    fn control_characters_move_the_cursor()
+    {
+        use std::cell::Cell as Counter;
+        use std::rc::Rc;
+
+        let rings = Rc::new(Counter::new(0));
+        let counter = rings.clone();
+        let mut p = panel(12, 3);
+        p.set_bell(Box::new(move || counter.set(counter.get() + 1)));
+
+        p.write_string("ab\tc\x07\rX\x08\x08Y\n\x01\x1bz\x7f");
+        assert_eq!(text(&p), "Yb      c\nz\n");
+        assert_eq!(rings.get(), 1);
+
+        p.lf_returns = false;
+        p.write_string("\nw");
+        assert_eq!(text(&p), "Yb      c\nz\n w");
+
+        p.write_string("\x0cnew");
+        assert_eq!(text(&p), "new\n\n");
+    }
+
+    #[test]
+    fn tabs_stop_at_the_last_column()
+    {
+        let mut p = panel(12, 1);
+        p.set_tab_stops(&[5, 2, 5]);
+        assert_eq!(p.tab_stops, [2, 5]);
+        p.write_string("\ta\tb\tc\td");
+        assert_eq!(text(&p), "  a  b     d");
+
+        p.set_tab_width(4);
+        assert_eq!(p.tab_stops, [4, 8]);
+        assert_eq!(default_tab_stops(20), [8, 16]);
+    }
+
+    #[test]
+    fn fmt_write_and_panel_print()
+    {
+        use std::fmt::Write;
+
+        let mut p = panel(14, 2);
+        write!(p, "{}-{}", 1, 2).unwrap();
+        crate::panel_print!(&mut p, 3, 1, "Score: {:03}", 7);
+        assert_eq!(text(&p), "1-2\n   Score: 007");
+        assert_eq!((p.cursor_x, p.cursor_y), (13, 1));
+    }
+
+    #[test]
+    fn utf8_split_across_writes_is_joined()
+    {
+        use std::io::Write;
+
+        let mut p = panel(8, 1);
+        // U+00E9 is C3 A9, U+2588 is E2 96 88
+        assert_eq!(p.write(b"a\xc3").unwrap(), 2);
+        assert_eq!(text(&p), "a");
+        p.write_all(b"\xa9\xe2").unwrap();
+        p.write_all(b"\x96").unwrap();
+        p.write_all(b"\x88b").unwrap();
+        assert_eq!(text(&p), "a\u{e9}\u{2588}b");
+        assert!(p.utf8_pending.is_empty());
+    }
+
+    #[test]
+    fn invalid_utf8_becomes_replacement_characters()
+    {
+        use std::io::Write;
+
+        let mut p = panel(8, 1);
+        p.write_all(b"a\xffb\xc3(c").unwrap();
+        assert_eq!(text(&p), "a\u{fffd}b\u{fffd}(c");
+    }
+
+    #[test]
+    fn incomplete_sequences_stay_pending_over_a_flush()
+    {
+        use std::io::Write;
+
+        let mut p = panel(8, 1);
+        p.write_all(b"x\xe2\x96").unwrap();
+        p.flush().unwrap();
+        assert_eq!(text(&p), "x");
+        assert_eq!(p.utf8_pending, b"\xe2\x96");
+
+        p.write_all(b"\x88").unwrap();
+        assert_eq!(text(&p), "x\u{2588}");
+        assert!(p.utf8_pending.is_empty());
+    }
 }
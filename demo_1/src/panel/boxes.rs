@@ -0,0 +1,420 @@
+//! Borders and lines drawn with box-drawing characters.
+//!
+//! Every border cell is described by the sides a line leaves it through
+//! and how heavy each of those lines is. Where a border crosses a line
+//! already on the surface the two are merged, so a divider drawn across a
+//! box turns its edges into `├` and `┤` instead of cutting them. Every
+//! style except `Ascii` needs a font with the U+2500 block, such as the
+//! built-in Apple II 40 column font.
+
+use macroquad::prelude::*;
+
+use crate::panel::Attrs;
+use crate::surface::TextSurface;
+
+const LIGHT: u8 = 1;
+const HEAVY: u8 = 2;
+const DOUBLE: u8 = 3;
+
+const UP: usize = 0;
+const RIGHT: usize = 1;
+const DOWN: usize = 2;
+const LEFT: usize = 3;
+
+/// The weight of the line leaving a cell upwards, to the right, downwards
+/// and to the left, or 0 for none.
+type Arms = [u8; 4];
+
+/// Every box-drawing character made of whole lines, by the lines in it.
+const JUNCTIONS: &[(char, Arms)] = &[
+    ('─', [0, 1, 0, 1]), ('━', [0, 2, 0, 2]), ('│', [1, 0, 1, 0]), ('┃', [2, 0, 2, 0]),
+    ('┌', [0, 1, 1, 0]), ('┍', [0, 2, 1, 0]), ('┎', [0, 1, 2, 0]), ('┏', [0, 2, 2, 0]),
+    ('┐', [0, 0, 1, 1]), ('┑', [0, 0, 1, 2]), ('┒', [0, 0, 2, 1]), ('┓', [0, 0, 2, 2]),
+    ('└', [1, 1, 0, 0]), ('┕', [1, 2, 0, 0]), ('┖', [2, 1, 0, 0]), ('┗', [2, 2, 0, 0]),
+    ('┘', [1, 0, 0, 1]), ('┙', [1, 0, 0, 2]), ('┚', [2, 0, 0, 1]), ('┛', [2, 0, 0, 2]),
+    ('├', [1, 1, 1, 0]), ('┝', [1, 2, 1, 0]), ('┞', [2, 1, 1, 0]), ('┟', [1, 1, 2, 0]),
+    ('┠', [2, 1, 2, 0]), ('┡', [2, 2, 1, 0]), ('┢', [1, 2, 2, 0]), ('┣', [2, 2, 2, 0]),
+    ('┤', [1, 0, 1, 1]), ('┥', [1, 0, 1, 2]), ('┦', [2, 0, 1, 1]), ('┧', [1, 0, 2, 1]),
+    ('┨', [2, 0, 2, 1]), ('┩', [2, 0, 1, 2]), ('┪', [1, 0, 2, 2]), ('┫', [2, 0, 2, 2]),
+    ('┬', [0, 1, 1, 1]), ('┭', [0, 1, 1, 2]), ('┮', [0, 2, 1, 1]), ('┯', [0, 2, 1, 2]),
+    ('┰', [0, 1, 2, 1]), ('┱', [0, 1, 2, 2]), ('┲', [0, 2, 2, 1]), ('┳', [0, 2, 2, 2]),
+    ('┴', [1, 1, 0, 1]), ('┵', [1, 1, 0, 2]), ('┶', [1, 2, 0, 1]), ('┷', [1, 2, 0, 2]),
+    ('┸', [2, 1, 0, 1]), ('┹', [2, 1, 0, 2]), ('┺', [2, 2, 0, 1]), ('┻', [2, 2, 0, 2]),
+    ('┼', [1, 1, 1, 1]), ('┽', [1, 1, 1, 2]), ('┾', [1, 2, 1, 1]), ('┿', [1, 2, 1, 2]),
+    ('╀', [2, 1, 1, 1]), ('╁', [1, 1, 2, 1]), ('╂', [2, 1, 2, 1]), ('╃', [2, 1, 1, 2]),
+    ('╄', [2, 2, 1, 1]), ('╅', [1, 1, 2, 2]), ('╆', [1, 2, 2, 1]), ('╇', [2, 2, 1, 2]),
+    ('╈', [1, 2, 2, 2]), ('╉', [2, 1, 2, 2]), ('╊', [2, 2, 2, 1]), ('╋', [2, 2, 2, 2]),
+    ('═', [0, 3, 0, 3]), ('║', [3, 0, 3, 0]), ('╒', [0, 3, 1, 0]), ('╓', [0, 1, 3, 0]),
+    ('╔', [0, 3, 3, 0]), ('╕', [0, 0, 1, 3]), ('╖', [0, 0, 3, 1]), ('╗', [0, 0, 3, 3]),
+    ('╘', [1, 3, 0, 0]), ('╙', [3, 1, 0, 0]), ('╚', [3, 3, 0, 0]), ('╛', [1, 0, 0, 3]),
+    ('╜', [3, 0, 0, 1]), ('╝', [3, 0, 0, 3]), ('╞', [1, 3, 1, 0]), ('╟', [3, 1, 3, 0]),
+    ('╠', [3, 3, 3, 0]), ('╡', [1, 0, 1, 3]), ('╢', [3, 0, 3, 1]), ('╣', [3, 0, 3, 3]),
+    ('╤', [0, 3, 1, 3]), ('╥', [0, 1, 3, 1]), ('╦', [0, 3, 3, 3]), ('╧', [1, 3, 0, 3]),
+    ('╨', [3, 1, 0, 1]), ('╩', [3, 3, 0, 3]), ('╪', [1, 3, 1, 3]), ('╫', [3, 1, 3, 1]),
+    ('╬', [3, 3, 3, 3]),
+];
+
+const ROUNDED_CORNERS: &[(char, Arms)] = &[
+    ('╭', [0, 1, 1, 0]), ('╮', [0, 0, 1, 1]), ('╯', [1, 0, 0, 1]), ('╰', [1, 1, 0, 0]),
+];
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Default)]
+pub enum BorderStyle {
+    /// `+`, `-` and `|`, for fonts without box-drawing glyphs.
+    #[default]
+    Ascii,
+    Single,
+    Double,
+    Heavy,
+    /// Single lines with rounded corners.
+    Rounded,
+}
+
+impl BorderStyle {
+    fn weight(self) -> u8
+    {
+        match self {
+            BorderStyle::Ascii | BorderStyle::Single | BorderStyle::Rounded => LIGHT,
+            BorderStyle::Double => DOUBLE,
+            BorderStyle::Heavy => HEAVY,
+        }
+    }
+}
+
+/// How to draw a box, e.g.
+/// `BoxOptions::new(BorderStyle::Double).title("Settings").shadow(BLACK)`.
+#[derive(Debug, Clone, PartialEq)]
+pub struct BoxOptions {
+    pub style: BorderStyle,
+
+    /// Written into the top edge, starting two cells in and cut short to
+    /// leave two cells at the other end.
+    pub title: Option<String>,
+
+    /// Whether the border joins lines already on the surface. When off,
+    /// it overwrites them.
+    pub merge: bool,
+
+    /// Casts a shadow one cell below and to the right of the box by
+    /// dimming the cells there and giving them this background.
+    pub shadow: Option<Color>,
+}
+
+impl BoxOptions {
+    /// A plain, untitled box in `style` that merges with other lines.
+    pub fn new(style: BorderStyle) -> BoxOptions
+    {
+        BoxOptions {
+            style,
+            title: None,
+            merge: true,
+            shadow: None,
+        }
+    }
+
+    pub fn title(mut self, title: &str) -> BoxOptions
+    {
+        self.title = Some(title.to_string());
+        self
+    }
+
+    pub fn merge(mut self, merge: bool) -> BoxOptions
+    {
+        self.merge = merge;
+        self
+    }
+
+    pub fn shadow(mut self, color: Color) -> BoxOptions
+    {
+        self.shadow = Some(color);
+        self
+    }
+}
+
+/// Draws the border of the `w` by `h` box at `x`, `y` in the surface's
+/// current colors, leaving the inside alone. A box one cell high is drawn
+/// as a horizontal line, and one a cell wide as a vertical line.
+pub fn draw_box<S: TextSurface + ?Sized>(surface: &mut S, x: u32, y: u32, w: u32, h: u32,
+                                         options: &BoxOptions)
+{
+    if w == 0 || h == 0 {
+        return;
+    }
+    let weight = options.style.weight();
+    let right = x.saturating_add(w - 1);
+    let bottom = y.saturating_add(h - 1);
+    // only the perimeter is visited, and only as far as the surface goes
+    let (width, height) = surface.size();
+
+    let mut edge = |cx: u32, cy: u32| {
+        let mut arms = [0; 4];
+        if cx == x || cx == right {
+            arms[UP] = if cy > y { weight } else { 0 };
+            arms[DOWN] = if cy < bottom { weight } else { 0 };
+        }
+        if cy == y || cy == bottom {
+            arms[LEFT] = if cx > x { weight } else { 0 };
+            arms[RIGHT] = if cx < right { weight } else { 0 };
+        }
+        if arms == [0; 4] {
+            // a single cell
+            arms[LEFT] = weight;
+            arms[RIGHT] = weight;
+        }
+        put_line(surface, cx, cy, arms, options.style, options.merge);
+    };
+    for cx in x..right.saturating_add(1).min(width) {
+        edge(cx, y);
+        if bottom != y {
+            edge(cx, bottom);
+        }
+    }
+    for cy in y.saturating_add(1)..bottom.min(height) {
+        edge(x, cy);
+        if right != x {
+            edge(right, cy);
+        }
+    }
+
+    if let Some(title) = &options.title {
+        let mut cell = surface.blank_cell();
+        let room = (right - x).saturating_sub(3) as usize;
+        for (i, c) in format!(" {} ", title).chars().take(room).enumerate() {
+            cell.ch = c;
+            surface.put_cell(cell, x + 2 + i as u32, y);
+        }
+    }
+
+    let shadow_corner = x.checked_add(w).zip(y.checked_add(h));
+    if let (Some(color), Some((shadow_x, shadow_y))) = (options.shadow, shadow_corner) {
+        let below = (x + 1..=shadow_x).map(|cx| (cx, shadow_y));
+        let beside = (y + 1..shadow_y).map(|cy| (shadow_x, cy));
+        for (cx, cy) in below.chain(beside) {
+            if let Some(mut cell) = surface.get_cell(cx, cy) {
+                cell.bg = Some(color.into());
+                cell.attrs.insert(Attrs::DIM);
+                surface.put_cell(cell, cx, cy);
+            }
+        }
+    }
+}
+
+/// Draws a horizontal line `len` cells long from `x`, `y`, joining any
+/// lines it meets.
+pub fn draw_hline<S: TextSurface + ?Sized>(surface: &mut S, x: u32, y: u32, len: u32,
+                                           style: BorderStyle)
+{
+    let weight = style.weight();
+    let end = x.saturating_add(len);
+    for cx in x..end.min(surface.size().0) {
+        let mut arms = [0; 4];
+        arms[LEFT] = if cx > x { weight } else { 0 };
+        arms[RIGHT] = if cx + 1 < end { weight } else { 0 };
+        put_line(surface, cx, y, arms, style, true);
+    }
+}
+
+/// Draws a vertical line `len` cells long from `x`, `y`, joining any
+/// lines it meets.
+pub fn draw_vline<S: TextSurface + ?Sized>(surface: &mut S, x: u32, y: u32, len: u32,
+                                           style: BorderStyle)
+{
+    let weight = style.weight();
+    let end = y.saturating_add(len);
+    for cy in y..end.min(surface.size().1) {
+        let mut arms = [0; 4];
+        arms[UP] = if cy > y { weight } else { 0 };
+        arms[DOWN] = if cy + 1 < end { weight } else { 0 };
+        put_line(surface, x, cy, arms, style, true);
+    }
+}
+
+/// Puts the character for `arms`, plus the lines already in the cell if
+/// `merge` is set, at `x`, `y`.
+fn put_line<S: TextSurface + ?Sized>(surface: &mut S, x: u32, y: u32, arms: Arms,
+                                     style: BorderStyle, merge: bool)
+{
+    let Some(existing) = surface.get_cell(x, y) else {
+        return;
+    };
+    if arms == [0; 4] {
+        return;
+    }
+
+    let mut combined = arms;
+    if merge {
+        if let Some(old) = arms_of(existing.ch, style) {
+            for (new, old) in combined.iter_mut().zip(old) {
+                if *new == 0 {
+                    *new = old;
+                }
+            }
+        }
+    }
+
+    let mut cell = surface.blank_cell();
+    cell.ch = line_char(combined, style);
+    surface.put_cell(cell, x, y);
+}
+
+/// The lines in `c`, if it's a character `style` can join onto.
+fn arms_of(c: char, style: BorderStyle) -> Option<Arms>
+{
+    if style == BorderStyle::Ascii {
+        return match c {
+            '-' => Some([0, LIGHT, 0, LIGHT]),
+            '|' => Some([LIGHT, 0, LIGHT, 0]),
+            '+' => Some([LIGHT; 4]),
+            _ => None,
+        };
+    }
+    JUNCTIONS.iter()
+        .chain(ROUNDED_CORNERS)
+        .find(|&&(ch, _)| ch == c)
+        .map(|&(_, arms)| arms)
+}
+
+/// The character for a cell with lines `arms`. Combinations Unicode has
+/// no character for, such as heavy meeting double, are drawn all in the
+/// weight of `style`, and a line stopping in the middle of a cell is run
+/// through it.
+fn line_char(arms: Arms, style: BorderStyle) -> char
+{
+    let horizontal = arms[LEFT] != 0 || arms[RIGHT] != 0;
+    let vertical = arms[UP] != 0 || arms[DOWN] != 0;
+
+    if style == BorderStyle::Ascii {
+        return match (horizontal, vertical) {
+            (true, true) => '+',
+            (true, false) => '-',
+            _ => '|',
+        };
+    }
+    if style == BorderStyle::Rounded {
+        if let Some(&(c, _)) = ROUNDED_CORNERS.iter().find(|&&(_, a)| a == arms) {
+            return c;
+        }
+    }
+
+    let find = |arms: Arms| JUNCTIONS.iter().find(|&&(_, a)| a == arms).map(|&(c, _)| c);
+    let weight = style.weight();
+    let uniform = arms.map(|a| if a == 0 { 0 } else { weight });
+    let mut through = uniform;
+    if vertical {
+        through[UP] = weight;
+        through[DOWN] = weight;
+    }
+    if horizontal {
+        through[LEFT] = weight;
+        through[RIGHT] = weight;
+    }
+    find(arms)
+        .or_else(|| find(uniform))
+        .or_else(|| find(through))
+        .unwrap_or(' ')
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::assert_panel_eq;
+    use crate::font::builtin;
+    use crate::panel::Panel;
+
+    fn panel(w: u32, h: u32) -> Panel
+    {
+        Panel::builder(builtin::apple2_40col(), w, h).build()
+    }
+
+    #[test]
+    fn boxes_leave_the_inside_alone()
+    {
+        let mut p = panel(6, 4);
+        p.fill_char('x', 0, 0, 6, 4);
+        draw_box(&mut p, 0, 0, 5, 4, &BoxOptions::new(BorderStyle::Single));
+        assert_panel_eq!(&p, "┌───┐x\n│xxx│x\n│xxx│x\n└───┘x");
+    }
+
+    #[test]
+    fn junctions_merge()
+    {
+        let mut p = panel(7, 5);
+        draw_box(&mut p, 0, 0, 5, 5, &BoxOptions::new(BorderStyle::Single));
+        draw_hline(&mut p, 0, 2, 5, BorderStyle::Single);
+        draw_box(&mut p, 2, 1, 5, 3, &BoxOptions::new(BorderStyle::Double));
+        assert_panel_eq!(&p, "\
+┌───┐
+│ ╔═╪═╗
+├─╫─┤ ║
+│ ╚═╪═╝
+└───┘");
+    }
+
+    #[test]
+    fn unmerged_boxes_overwrite()
+    {
+        let mut p = panel(5, 3);
+        draw_box(&mut p, 0, 0, 5, 3, &BoxOptions::new(BorderStyle::Ascii));
+        draw_vline(&mut p, 2, 0, 3, BorderStyle::Ascii);
+        draw_box(&mut p, 2, 0, 3, 3, &BoxOptions::new(BorderStyle::Heavy).merge(false));
+        assert_panel_eq!(&p, "+-┏━┓\n| ┃ ┃\n+-┗━┛");
+    }
+
+    #[test]
+    fn titles_are_cut_to_fit()
+    {
+        let mut p = panel(10, 3);
+        let options = BoxOptions::new(BorderStyle::Rounded).title("Settings");
+        draw_box(&mut p, 0, 0, 10, 2, &options);
+        draw_box(&mut p, 0, 2, 4, 1, &options);
+        assert_panel_eq!(&p, "╭─ Setti─╮\n╰────────╯\n────");
+
+        let mut p = panel(14, 2);
+        draw_box(&mut p, 0, 0, 14, 2, &options);
+        assert_panel_eq!(&p, "╭─ Settings ─╮\n╰────────────╯");
+    }
+
+    #[test]
+    fn thin_boxes_are_lines()
+    {
+        let mut p = panel(4, 4);
+        let options = BoxOptions::new(BorderStyle::Single);
+        draw_box(&mut p, 0, 0, 1, 1, &options);
+        draw_box(&mut p, 1, 1, 3, 1, &options);
+        draw_box(&mut p, 3, 2, 1, 2, &options);
+        draw_box(&mut p, 2, 2, 0, 2, &options);
+        assert_panel_eq!(&p, "─\n ───\n   │\n   │");
+    }
+
+    #[test]
+    fn shadows_dim_below_and_beside()
+    {
+        let mut p = panel(4, 3);
+        draw_box(&mut p, 0, 0, 3, 2, &BoxOptions::new(BorderStyle::Ascii).shadow(BLACK));
+        let shaded: Vec<(u32, u32)> = (0..3)
+            .flat_map(|y| (0..4).map(move |x| (x, y)))
+            .filter(|&(x, y)| p.cells[y as usize][x as usize].attrs.contains(Attrs::DIM))
+            .collect();
+        assert_eq!(shaded, [(3, 1), (1, 2), (2, 2), (3, 2)]);
+    }
+
+    #[test]
+    fn boxes_at_the_edge_of_the_coordinate_space()
+    {
+        let mut p = panel(4, 4);
+        let options = BoxOptions::new(BorderStyle::Single).title("t").shadow(BLACK);
+        draw_box(&mut p, u32::MAX - 1, 0, 8, 8, &options);
+        draw_box(&mut p, 2, 2, u32::MAX, u32::MAX, &options);
+        assert_panel_eq!(&p, "\n\n  ┌─\n  │");
+    }
+
+    #[test]
+    fn lines_stop_at_the_edge()
+    {
+        let mut p = panel(4, 3);
+        draw_hline(&mut p, 2, 0, u32::MAX, BorderStyle::Single);
+        draw_vline(&mut p, 1, 1, u32::MAX, BorderStyle::Double);
+        draw_hline(&mut p, u32::MAX, 2, 4, BorderStyle::Single);
+        assert_panel_eq!(&p, "  ──\n ║\n ║");
+    }
+}
@@ -0,0 +1,222 @@
+//! Text snapshots of surfaces, for checking what a screen shows without
+//! rendering it.
+//!
+//! A plain snapshot is just the characters, one line per row. A styled
+//! snapshot puts a style line under each row, giving every cell a letter,
+//! followed by a legend of what each letter means:
+//!
+//! ```text
+//! |Hello   |
+//! |aaaaabbb|
+//! a: fg=00e430ff bg=none
+//! b: fg=00e430ff bg=000000ff inverse
+//! ```
+//!
+//! Palette indices are shown as `[n]`, e.g. `fg=[15]`.
+
+use crate::palette::CellColor;
+use crate::panel::{Attrs, Cell};
+use crate::surface::TextSurface;
+
+const STYLE_LETTERS: &str = "abcdefghijklmnopqrstuvwxyzABCDEFGHIJKLMNOPQRSTUVWXYZ0123456789";
+
+/// The surface's characters, one line per row. With `trim` set, trailing
+/// spaces are dropped from each line.
+pub fn text_snapshot<S: TextSurface + ?Sized>(surface: &S, trim: bool) -> String
+{
+    let (width, height) = surface.size();
+    let mut lines = vec![];
+    for y in 0..height {
+        let line: String = (0..width)
+            .map(|x| surface.get_cell(x, y).map_or(' ', |cell| cell.ch))
+            .collect();
+        lines.push(if trim { line.trim_end().to_string() } else { line });
+    }
+    lines.join("\n")
+}
+
+/// The surface's characters, colors and attributes, in the format
+/// described above.
+pub fn styled_snapshot<S: TextSurface + ?Sized>(surface: &S) -> String
+{
+    let (width, height) = surface.size();
+    let mut styles: Vec<(CellColor, Option<CellColor>, Attrs)> = vec![];
+    let mut out = String::new();
+
+    for y in 0..height {
+        let cells: Vec<Cell> = (0..width)
+            .filter_map(|x| surface.get_cell(x, y))
+            .collect();
+
+        out.push('|');
+        out.extend(cells.iter().map(|cell| cell.ch));
+        out.push_str("|\n|");
+        for cell in &cells {
+            let style = (cell.fg, cell.bg, cell.attrs);
+            let index = match styles.iter().position(|&s| s == style) {
+                Some(index) => index,
+                None => {
+                    styles.push(style);
+                    styles.len() - 1
+                }
+            };
+            out.push(style_letter(index));
+        }
+        out.push_str("|\n");
+    }
+
+    for (index, (fg, bg, attrs)) in styles.iter().enumerate() {
+        out.push(style_letter(index));
+        out.push_str(": fg=");
+        out.push_str(&color_name(*fg));
+        out.push_str(" bg=");
+        out.push_str(&bg.map_or("none".to_string(), color_name));
+        for (flag, name) in [(Attrs::INVERSE, "inverse"), (Attrs::FLASH, "flash"),
+                             (Attrs::UNDERLINE, "underline"), (Attrs::DIM, "dim")] {
+            if attrs.contains(flag) {
+                out.push(' ');
+                out.push_str(name);
+            }
+        }
+        out.push('\n');
+    }
+    out
+}
+
+/// Letters for the first 62 styles, then arbitrary symbols past U+0100.
+fn style_letter(index: usize) -> char
+{
+    STYLE_LETTERS.chars().nth(index)
+        .or_else(|| char::from_u32(0x100 + index as u32))
+        .unwrap_or('?')
+}
+
+/// `rrggbbaa` in hex, or a palette index in brackets.
+fn color_name(color: CellColor) -> String
+{
+    match color {
+        CellColor::Rgb(color) => {
+            let [r, g, b, a]: [u8; 4] = color.into();
+            format!("{:02x}{:02x}{:02x}{:02x}", r, g, b, a)
+        }
+        CellColor::Index(index) => format!("[{}]", index),
+    }
+}
+
+/// Compares two snapshots line by line, ignoring trailing spaces and
+/// trailing blank lines. Returns `None` if they match, otherwise a listing
+/// of every row with the differing ones marked `-` (expected) and `+`
+/// (actual).
+pub fn snapshot_diff(actual: &str, expected: &str) -> Option<String>
+{
+    let normalize = |s: &str| -> Vec<String> {
+        let mut lines: Vec<String> = s.lines().map(|l| l.trim_end().to_string()).collect();
+        while lines.last().is_some_and(|l| l.is_empty()) {
+            lines.pop();
+        }
+        lines
+    };
+    let actual = normalize(actual);
+    let expected = normalize(expected);
+    if actual == expected {
+        return None;
+    }
+
+    let mut out = String::new();
+    for row in 0..actual.len().max(expected.len()) {
+        let a = actual.get(row).map(String::as_str);
+        let e = expected.get(row).map(String::as_str);
+        if a == e {
+            out.push_str(&format!("  {:3} {}\n", row, a.unwrap_or_default()));
+            continue;
+        }
+        if let Some(e) = e {
+            out.push_str(&format!("- {:3} {}\n", row, e));
+        }
+        if let Some(a) = a {
+            out.push_str(&format!("+ {:3} {}\n", row, a));
+        }
+    }
+    Some(out)
+}
+
+/// Asserts that a surface shows the expected text, e.g.
+/// `assert_panel_eq!(&menu, "  load\n> settings")`, printing a row by row
+/// diff if not. Trailing spaces don't count. Prefix the surface with
+/// `styled` to compare against a styled snapshot instead.
+#[macro_export]
+macro_rules! assert_panel_eq {
+    (styled $surface:expr, $expected:expr) => {{
+        let actual = $crate::snapshot::styled_snapshot($surface);
+        if let Some(diff) = $crate::snapshot::snapshot_diff(&actual, $expected) {
+            panic!("styled snapshot doesn't match (- expected, + actual):\n{}", diff);
+        }
+    }};
+    ($surface:expr, $expected:expr) => {{
+        let actual = $crate::snapshot::text_snapshot($surface, true);
+        if let Some(diff) = $crate::snapshot::snapshot_diff(&actual, $expected) {
+            panic!("panel text doesn't match (- expected, + actual):\n{}", diff);
+        }
+    }};
+}
+
+#[cfg(test)]
+mod tests {
+    use macroquad::prelude::*;
+
+    use super::*;
+    use crate::font::builtin;
+    use crate::panel::Panel;
+
+    fn panel(w: u32, h: u32) -> Panel
+    {
+        Panel::builder(builtin::apple2_40col(), w, h).font_color(WHITE).build()
+    }
+
+    #[test]
+    fn plain_snapshots()
+    {
+        let mut p = panel(8, 3);
+        p.write_string("Hello");
+        p.set_cursor_pos(2, 2);
+        p.write_string("there");
+        assert_panel_eq!(&p, "Hello\n\n  there");
+        assert_eq!(text_snapshot(&p, false), "Hello   \n        \n  there ");
+        assert_eq!(p.to_text(true), "Hello\n\n  there");
+    }
+
+    #[test]
+    fn styled_snapshots()
+    {
+        let mut p = panel(4, 2);
+        p.write_string("ab");
+        p.set_colors(RED, Some(BLACK));
+        p.write_string_attrs("c", Attrs::INVERSE | Attrs::UNDERLINE);
+        assert_panel_eq!(styled &p, "\
+|abc |
+|aaba|
+|    |
+|aaaa|
+a: fg=ffffffff bg=none
+b: fg=e52838ff bg=000000ff inverse underline
+");
+        assert_eq!(p.to_styled_text(), styled_snapshot(&p));
+    }
+
+    #[test]
+    fn diffs_mark_changed_rows()
+    {
+        assert_eq!(snapshot_diff("a  \nb\n\n", "a\nb"), None);
+        assert_eq!(snapshot_diff("a\nc", "a\nb\nd").unwrap(),
+                   "    0 a\n-   1 b\n+   1 c\n-   2 d\n");
+    }
+
+    #[test]
+    #[should_panic(expected = "-   0 goodbye\n+   0 hello")]
+    fn mismatches_panic_with_a_diff()
+    {
+        let mut p = panel(8, 1);
+        p.write_string("hello");
+        assert_panel_eq!(&p, "goodbye");
+    }
+}
@@ -0,0 +1,215 @@
+//! Drawing panels on the CPU, for tests and tools that run without a
+//! window or GPU.
+//!
+//! `render_panel` produces the same pixels `Panel::draw` puts on screen:
+//! colors are quantized to 8 bits per channel the way macroquad's vertex
+//! colors are, glyphs are sampled nearest-neighbour from the atlas and
+//! everything is alpha blended in the same order. The cursor is left out;
+//! `render_cursor` adds it. Indexed colors come from `Panel::palette` as
+//! it is, without palette cycles, since there's no clock here; set the
+//! palette to `Panel::palette_at` some time first to render a later
+//! frame.
+
+use macroquad::prelude::*;
+
+use crate::cursor::cursor_paint;
+use crate::panel::{resolve_cell_colors, Attrs, Panel};
+
+/// Renders `panel` into an RGBA image the size of the panel on screen,
+/// with its top left cell at 0, 0. Pixels the panel doesn't cover are
+/// transparent. `flash_on` picks which phase flashing cells are drawn in.
+/// Returns `None` if the panel is more than 65535 pixels across or down,
+/// the largest image macroquad can hold.
+pub fn render_panel(panel: &Panel, flash_on: bool) -> Option<Image>
+{
+    let scale = panel.pixel_scale;
+    let cell_w = panel.font.width.checked_mul(scale)?;
+    let cell_h = panel.font.height.checked_mul(scale)?;
+    let width = u16::try_from(panel.char_width.checked_mul(cell_w)?).ok()?;
+    let height = u16::try_from(panel.char_height.checked_mul(cell_h)?).ok()?;
+    let mut target = Image::gen_image_color(width, height, BLANK);
+    let (width, height) = (width as u32, height as u32);
+
+    if let Some(erase_color) = panel.erase_color {
+        fill_rect(&mut target, 0, 0, width, height, erase_color);
+    }
+
+    let underline_y = panel.font.underline_row() * scale;
+
+    for x in 0..panel.char_width {
+        for y in 0..panel.char_height {
+            let cell = panel.cells[y as usize][x as usize];
+            let sx = x * cell_w;
+            let sy = y * cell_h;
+
+            let (fg, bg) = resolve_cell_colors(&cell, &panel.palette, panel.erase_color, flash_on);
+
+            if let Some(bg) = bg {
+                fill_rect(&mut target, sx, sy, cell_w, cell_h, bg);
+            }
+            draw_glyph(&mut target, panel, cell.ch, fg, sx, sy);
+            if cell.attrs.contains(Attrs::UNDERLINE) {
+                fill_rect(&mut target, sx, sy + underline_y, cell_w, scale, fg);
+            }
+        }
+    }
+
+    Some(target)
+}
+
+/// Draws the panel's cursor, as it looks in the on phase of its blink,
+/// over an image from `render_panel`. Does nothing if it's hidden.
+pub fn render_cursor(target: &mut Image, panel: &Panel, flash_on: bool)
+{
+    let Some(paint) = cursor_paint(panel, &panel.palette, flash_on) else {
+        return;
+    };
+    let sx = paint.cell.0 * panel.font.width * panel.pixel_scale;
+    let sy = paint.cell.1 * panel.font.height * panel.pixel_scale;
+    for (rect, color) in paint.rects {
+        fill_rect(target, sx + rect.x as u32, sy + rect.y as u32, rect.w as u32, rect.h as u32, color);
+    }
+    if let Some((c, color)) = paint.glyph {
+        draw_glyph(target, panel, c, color, sx, sy);
+    }
+}
+
+/// Blends `src`, already multiplied by the vertex color, over the pixel
+/// at `x`, `y` with source-over alpha, like the default pipeline.
+fn blend_pixel(target: &mut Image, x: u32, y: u32, src: [f32; 4])
+{
+    if x >= target.width as u32 || y >= target.height as u32 {
+        return;
+    }
+    let width = target.width as usize;
+    let dst = &mut target.get_image_data_mut()[y as usize * width + x as usize];
+    let alpha = src[3];
+    for (d, s) in dst.iter_mut().zip(src) {
+        let out = s * alpha + (*d as f32 / 255.0) * (1.0 - alpha);
+        *d = (out.clamp(0.0, 1.0) * 255.0).round() as u8;
+    }
+}
+
+fn vertex_color(color: Color) -> [f32; 4]
+{
+    let bytes: [u8; 4] = color.into();
+    bytes.map(|b| b as f32 / 255.0)
+}
+
+fn fill_rect(target: &mut Image, x: u32, y: u32, w: u32, h: u32, color: Color)
+{
+    let color = vertex_color(color);
+    for py in y..y + h {
+        for px in x..x + w {
+            blend_pixel(target, px, py, color);
+        }
+    }
+}
+
+/// Draws the glyph for `c`, or the replacement glyph, with its top left
+/// corner at `x`, `y`, each atlas pixel becoming a `pixel_scale` square.
+fn draw_glyph(target: &mut Image, panel: &Panel, c: char, color: Color, x: u32, y: u32)
+{
+    let Some((page, source)) = panel.font.find_glyph_or_replacement(c) else {
+        return;
+    };
+    let atlas = page.atlas.image();
+    let pixels = atlas.get_image_data();
+    let atlas_w = atlas.width as u32;
+    let atlas_h = atlas.height as u32;
+    let tint = vertex_color(color);
+    let scale = panel.pixel_scale;
+
+    for gy in 0..panel.font.height {
+        for gx in 0..panel.font.width {
+            let ax = source.x as u32 + gx;
+            let ay = source.y as u32 + gy;
+            if ax >= atlas_w || ay >= atlas_h {
+                continue;
+            }
+            let texel = pixels[(ay * atlas_w + ax) as usize];
+            if texel[3] == 0 {
+                continue;
+            }
+            let src = [0, 1, 2, 3].map(|i| texel[i] as f32 / 255.0 * tint[i]);
+            for py in 0..scale {
+                for px in 0..scale {
+                    blend_pixel(target, x + gx * scale + px, y + gy * scale + py, src);
+                }
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::font::builtin;
+    use crate::panel::{Cell, TextSurface};
+
+    /// One character per pixel, from `legend`, or `?` for other colors.
+    fn pixels(image: &Image, legend: &[(char, [u8; 4])]) -> String
+    {
+        let mut out = String::new();
+        for row in image.get_image_data().chunks(image.width as usize) {
+            for pixel in row {
+                out.push(legend.iter().find(|(_, p)| p == pixel).map_or('?', |&(c, _)| c));
+            }
+            out.push('\n');
+        }
+        out
+    }
+
+    #[test]
+    fn golden_cells()
+    {
+        let mut panel = Panel::builder(builtin::apple2_40col(), 4, 1)
+            .erase_color(None)
+            .build();
+        for (x, attrs) in [Attrs::NONE, Attrs::INVERSE, Attrs::DIM, Attrs::UNDERLINE].into_iter().enumerate() {
+            panel.put_cell(Cell::with_attrs('A', WHITE.into(), None, attrs), x as u32, 0);
+        }
+        let image = render_panel(&panel, false).unwrap();
+        assert_eq!((image.width, image.height), (24, 8));
+
+        let legend = [('.', [0, 0, 0, 0]), ('#', [255; 4]), ('k', [0, 0, 0, 255]),
+                      ('d', [127, 127, 127, 255])];
+        assert_eq!(pixels(&image, &legend), "\
+..#...##k###..d.....#...
+.#.#..#k#k##.d.d...#.#..
+#...#.k###k#d...d.#...#.
+#...#.k###k#d...d.#...#.
+#####.kkkkk#ddddd.#####.
+#...#.k###k#d...d.#...#.
+#...#.k###k#d...d.#...#.
+......######......######
+");
+    }
+
+    #[test]
+    fn erase_color_and_scale()
+    {
+        let mut panel = Panel::builder(builtin::apple2_40col(), 1, 1)
+            .font_color(WHITE)
+            .erase_color(Some(Color::new(0.0, 0.0, 1.0, 0.5)))
+            .scale(2)
+            .build();
+        panel.put_cell(Cell::new('.', RED, None), 0, 0);
+        let image = render_panel(&panel, false).unwrap();
+
+        // colors are truncated to 8 bits like vertex colors, and alpha is
+        // blended with the same factors as the color channels
+        let legend = [('-', [0, 0, 127, 63]), ('r', [229, 40, 56, 255])];
+        let golden = "------------\n".repeat(12) + &"----rr------\n".repeat(2) + &"------------\n".repeat(2);
+        assert_eq!(pixels(&image, &legend), golden);
+    }
+
+    #[test]
+    fn oversized_panels_are_rejected()
+    {
+        let panel = Panel::builder(builtin::apple2_40col(), 11000, 1).build();
+        assert!(render_panel(&panel, false).is_none());
+        let panel = Panel::builder(builtin::apple2_40col(), 10000, 1).build();
+        assert_eq!(render_panel(&panel, false).map(|image| image.width), Some(60000));
+    }
+}
@@ -0,0 +1,286 @@
+//! A grid of character cells that can be written and read back, whatever
+//! ends up drawing it. `Panel` is one; widgets and drawing helpers should
+//! take a `TextSurface` so they also work on off-screen buffers and
+//! sub-views.
+
+use crate::panel::{Attrs, Cell};
+use crate::snapshot;
+
+pub trait TextSurface {
+    /// Width and height in cells.
+    fn size(&self) -> (u32, u32);
+
+    /// The cell at `x`, `y`, or `None` outside the surface.
+    fn get_cell(&self, x: u32, y: u32) -> Option<Cell>;
+
+    /// Replaces the cell at `x`, `y`. Cells outside the surface are
+    /// ignored.
+    fn put_cell(&mut self, cell: Cell, x: u32, y: u32);
+
+    fn cursor(&self) -> (u32, u32);
+
+    fn set_cursor_pos(&mut self, x: u32, y: u32);
+
+    /// The cell left behind by clearing: a space in the current colors.
+    fn blank_cell(&self) -> Cell;
+
+    /// Sets every cell in the `w` by `h` rectangle at `x`, `y` to `cell`,
+    /// clipped to the surface.
+    fn fill(&mut self, cell: Cell, x: u32, y: u32, w: u32, h: u32)
+    {
+        let (width, height) = self.size();
+        for cy in y..y.saturating_add(h).min(height) {
+            for cx in x..x.saturating_add(w).min(width) {
+                self.put_cell(cell, cx, cy);
+            }
+        }
+    }
+
+    /// Blanks the cells in the `w` by `h` rectangle at `x`, `y`.
+    fn clear_rect(&mut self, x: u32, y: u32, w: u32, h: u32)
+    {
+        self.fill(self.blank_cell(), x, y, w, h);
+    }
+
+    /// Copies the `w` by `h` rectangle at `x`, `y` so its top left corner
+    /// lands on `to_x`, `to_y`. The rectangles may overlap; the result is
+    /// as if the source were read in full before anything was written.
+    /// Cells that would come from or land outside the surface are left
+    /// out.
+    fn copy_rect(&mut self, x: u32, y: u32, w: u32, h: u32, to_x: u32, to_y: u32)
+    {
+        let (width, height) = self.size();
+        let w = w.min(width.saturating_sub(x)).min(width.saturating_sub(to_x));
+        let h = h.min(height.saturating_sub(y)).min(height.saturating_sub(to_y));
+
+        let mut source = Vec::with_capacity((w * h) as usize);
+        for cy in 0..h {
+            for cx in 0..w {
+                source.push(self.get_cell(x + cx, y + cy));
+            }
+        }
+        for (i, cell) in source.into_iter().enumerate() {
+            if let Some(cell) = cell {
+                let i = i as u32;
+                self.put_cell(cell, to_x + i % w, to_y + i / w);
+            }
+        }
+    }
+
+    /// Moves what's in the `w` by `h` rectangle at `x`, `y` by `dx`
+    /// columns and `dy` rows, e.g. `dy = -1` scrolls it up a line. Cells
+    /// moved past the edge of the rectangle are lost, and the ones
+    /// uncovered are blanked.
+    fn scroll_rect(&mut self, x: u32, y: u32, w: u32, h: u32, dx: i32, dy: i32)
+    {
+        let (width, height) = self.size();
+        let w = w.min(width.saturating_sub(x));
+        let h = h.min(height.saturating_sub(y));
+        let shift_x = dx.unsigned_abs().min(w);
+        let shift_y = dy.unsigned_abs().min(h);
+        let (from_x, to_x) = if dx < 0 { (x + shift_x, x) } else { (x, x + shift_x) };
+        let (from_y, to_y) = if dy < 0 { (y + shift_y, y) } else { (y, y + shift_y) };
+        self.copy_rect(from_x, from_y, w - shift_x, h - shift_y, to_x, to_y);
+
+        let blank = self.blank_cell();
+        let blank_x = if dx < 0 { x + w - shift_x } else { x };
+        let blank_y = if dy < 0 { y + h - shift_y } else { y };
+        self.fill(blank, blank_x, y, shift_x, h);
+        self.fill(blank, x, blank_y, w, shift_y);
+    }
+
+    /// Copies the `(x, y, w, h)` rectangle of `source` onto this surface
+    /// with its top left corner at `to_x`, `to_y`, skipping the cells
+    /// `transparency` says to let through.
+    fn blit(&mut self, source: &dyn TextSurface, (x, y, w, h): (u32, u32, u32, u32),
+            to_x: u32, to_y: u32, transparency: Transparency)
+    {
+        let (source_w, source_h) = source.size();
+        let (width, height) = self.size();
+        let w = w.min(source_w.saturating_sub(x)).min(width.saturating_sub(to_x));
+        let h = h.min(source_h.saturating_sub(y)).min(height.saturating_sub(to_y));
+        for cy in 0..h {
+            for cx in 0..w {
+                if let Some(cell) = source.get_cell(x + cx, y + cy) {
+                    if !transparency.lets_through(&cell) {
+                        self.put_cell(cell, to_x + cx, to_y + cy);
+                    }
+                }
+            }
+        }
+    }
+
+    /// Blanks every cell. The cursor doesn't move.
+    fn clear(&mut self)
+    {
+        let (width, height) = self.size();
+        self.fill(self.blank_cell(), 0, 0, width, height);
+    }
+
+    /// The characters shown, one line per row, e.g. for checking a screen
+    /// in a test. With `trim` set, trailing spaces are dropped.
+    fn to_text(&self, trim: bool) -> String
+    {
+        snapshot::text_snapshot(self, trim)
+    }
+
+    /// The characters along with their colors and attributes, in the
+    /// format described in the `snapshot` module.
+    fn to_styled_text(&self) -> String
+    {
+        snapshot::styled_snapshot(self)
+    }
+}
+
+/// Which cells of the source `TextSurface::blit` skips, leaving the
+/// destination showing through.
+#[derive(Clone, Copy, Default)]
+pub enum Transparency {
+    /// Every cell is copied.
+    #[default]
+    Opaque,
+    /// Spaces with no background color are skipped, unless they're
+    /// inverse, which shows the foreground color across them.
+    Spaces,
+    /// Cells showing this character are skipped, whatever their colors.
+    Key(char),
+    /// Cells the function returns `true` for are skipped.
+    Custom(fn(&Cell) -> bool),
+}
+
+impl Transparency {
+    pub fn lets_through(&self, cell: &Cell) -> bool
+    {
+        match self {
+            Transparency::Opaque => false,
+            Transparency::Spaces => {
+                cell.ch == ' ' && cell.bg.is_none() && !cell.attrs.contains(Attrs::INVERSE)
+            }
+            Transparency::Key(c) => cell.ch == *c,
+            Transparency::Custom(f) => f(cell),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use macroquad::prelude::*;
+
+    use super::*;
+    use crate::font::builtin;
+    use crate::panel::Panel;
+
+    /// A bare surface, to test the default methods `Panel` overrides.
+    struct Grid {
+        width: u32,
+        cells: Vec<Cell>,
+    }
+
+    impl TextSurface for Grid {
+        fn size(&self) -> (u32, u32)
+        {
+            (self.width, self.cells.len() as u32 / self.width)
+        }
+
+        fn get_cell(&self, x: u32, y: u32) -> Option<Cell>
+        {
+            let (width, height) = self.size();
+            (x < width && y < height).then(|| self.cells[(y * width + x) as usize])
+        }
+
+        fn put_cell(&mut self, cell: Cell, x: u32, y: u32)
+        {
+            let (width, height) = self.size();
+            if x < width && y < height {
+                self.cells[(y * width + x) as usize] = cell;
+            }
+        }
+
+        fn cursor(&self) -> (u32, u32)
+        {
+            (0, 0)
+        }
+
+        fn set_cursor_pos(&mut self, _x: u32, _y: u32) {}
+
+        fn blank_cell(&self) -> Cell
+        {
+            Cell::new(' ', WHITE, None)
+        }
+    }
+
+    /// A grid and a panel, both showing `rows`.
+    fn surfaces(rows: &[&str]) -> [Box<dyn TextSurface>; 2]
+    {
+        let width = rows[0].chars().count() as u32;
+        let mut grid = Grid { width, cells: vec![Cell::new(' ', WHITE, None); width as usize * rows.len()] };
+        let mut panel = Panel::builder(builtin::apple2_40col(), width, rows.len() as u32).build();
+        for (y, row) in rows.iter().enumerate() {
+            for (x, c) in row.chars().enumerate() {
+                let cell = Cell::new(c, WHITE, None);
+                grid.put_cell(cell, x as u32, y as u32);
+                panel.put_cell(cell, x as u32, y as u32);
+            }
+        }
+        [Box::new(grid), Box::new(panel)]
+    }
+
+    #[test]
+    fn copy_rect_overlapping()
+    {
+        for mut s in surfaces(&["abcdef", "ghijkl", "mnopqr"]) {
+            s.copy_rect(0, 0, 4, 2, 2, 1);
+            assert_eq!(s.to_text(false), "abcdef\nghabcd\nmnghij");
+            s.copy_rect(2, 1, 4, 2, 0, 0);
+            assert_eq!(s.to_text(false), "abcdef\nghijcd\nmnghij");
+        }
+    }
+
+    #[test]
+    fn copy_rect_clips_to_the_surface()
+    {
+        for mut s in surfaces(&["abcd", "efgh"]) {
+            s.copy_rect(1, 0, 10, 10, 2, 1);
+            assert_eq!(s.to_text(false), "abcd\nefbc");
+            s.copy_rect(5, 0, 2, 2, 0, 0);
+            s.copy_rect(0, 0, 0, 2, 1, 0);
+            s.copy_rect(0, 0, 2, 2, 4, 0);
+            assert_eq!(s.to_text(false), "abcd\nefbc");
+        }
+    }
+
+    #[test]
+    fn scroll_rect_blanks_what_it_uncovers()
+    {
+        for mut s in surfaces(&["abcde", "fghij", "klmno", "pqrst"]) {
+            s.scroll_rect(1, 1, 3, 3, 0, -1);
+            assert_eq!(s.to_text(false), "abcde\nflmnj\nkqrso\np   t");
+            s.scroll_rect(0, 0, 5, 2, 2, 1);
+            assert_eq!(s.to_text(false), "     \n  abc\nkqrso\np   t");
+            s.scroll_rect(0, 2, 5, 2, -9, 0);
+            assert_eq!(s.to_text(true), "\n  abc\n\n");
+        }
+    }
+
+    #[test]
+    fn blit_skips_transparent_cells()
+    {
+        let [source, _] = surfaces(&["a b", "#x#"]);
+        let inverse_space = Cell::with_attrs(' ', WHITE.into(), None, Attrs::INVERSE);
+
+        for mut s in surfaces(&["1234", "5678", "9012"]) {
+            s.blit(source.as_ref(), (0, 0, 3, 2), 1, 1, Transparency::Opaque);
+            assert_eq!(s.to_text(false), "1234\n5a b\n9#x#");
+            s.blit(source.as_ref(), (0, 1, 3, 1), 0, 0, Transparency::Key('#'));
+            assert_eq!(s.to_text(false), "1x34\n5a b\n9#x#");
+            s.blit(source.as_ref(), (0, 0, 3, 1), 1, 0, Transparency::Spaces);
+            assert_eq!(s.to_text(false), "1a3b\n5a b\n9#x#");
+            s.blit(source.as_ref(), (0, 0, 3, 2), 0, 0, Transparency::Custom(|cell| cell.ch != 'x'));
+            assert_eq!(s.to_text(false), "1a3b\n5x b\n9#x#");
+        }
+
+        assert!(Transparency::Spaces.lets_through(&Cell::new(' ', WHITE, None)));
+        assert!(!Transparency::Spaces.lets_through(&inverse_space));
+        assert!(!Transparency::Spaces.lets_through(&Cell::new(' ', WHITE, Some(BLACK))));
+    }
+}
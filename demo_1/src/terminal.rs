@@ -0,0 +1,268 @@
+//! A terminal emulator widget: a child process on a Linux pseudo-terminal
+//! whose output is interpreted into a `Panel`.
+//!
+//! ```ignore
+//! let mut term = Terminal::spawn("/bin/sh", &[], &mut panel)?;
+//! loop {
+//!     term.send_input()?;
+//!     term.update(&mut panel);
+//!     panel.draw();
+//!     next_frame().await
+//! }
+//! ```
+
+use std::ffi::CStr;
+use std::fs::File;
+use std::io::{self, Read, Write};
+use std::os::fd::{AsRawFd, FromRawFd};
+use std::os::unix::process::CommandExt;
+use std::process::{Child, Command, Stdio};
+use std::sync::mpsc::{self, Receiver, TryRecvError};
+use std::thread;
+
+use macroquad::prelude::*;
+
+use crate::ansi::AnsiParser;
+use crate::panel::Panel;
+
+pub struct Terminal {
+    master: File,
+    child: Child,
+    output: Receiver<Vec<u8>>,
+    parser: AnsiParser,
+    closed: bool,
+
+    /// The size last sent to the pseudo-terminal.
+    cols: u32,
+    rows: u32,
+}
+
+fn os_error(result: libc::c_int) -> io::Result<libc::c_int>
+{
+    if result < 0 {
+        Err(io::Error::last_os_error())
+    } else {
+        Ok(result)
+    }
+}
+
+fn set_window_size(master: &File, cols: u32, rows: u32) -> io::Result<()>
+{
+    let size = libc::winsize {
+        ws_row: rows as u16,
+        ws_col: cols as u16,
+        ws_xpixel: 0,
+        ws_ypixel: 0,
+    };
+    // SAFETY: the fd is open for the lifetime of `master` and `size` is a
+    // valid winsize.
+    os_error(unsafe { libc::ioctl(master.as_raw_fd(), libc::TIOCSWINSZ, &size) })?;
+    Ok(())
+}
+
+/// Opens a new pseudo-terminal pair, returning the master and slave ends.
+fn open_pty() -> io::Result<(File, File)>
+{
+    // SAFETY: plain libc calls; every returned fd is immediately owned by
+    // a File, and ptsname_r writes into a buffer of the size we pass.
+    unsafe {
+        let master_fd = os_error(libc::posix_openpt(libc::O_RDWR | libc::O_NOCTTY | libc::O_CLOEXEC))?;
+        let master = File::from_raw_fd(master_fd);
+        os_error(libc::grantpt(master_fd))?;
+        os_error(libc::unlockpt(master_fd))?;
+
+        let mut name = [0 as libc::c_char; 128];
+        let err = libc::ptsname_r(master_fd, name.as_mut_ptr(), name.len());
+        if err != 0 {
+            return Err(io::Error::from_raw_os_error(err));
+        }
+        let slave_fd = os_error(libc::open(CStr::from_ptr(name.as_ptr()).as_ptr(),
+                                           libc::O_RDWR | libc::O_NOCTTY | libc::O_CLOEXEC))?;
+        Ok((master, File::from_raw_fd(slave_fd)))
+    }
+}
+
+impl Terminal {
+    /// Starts `program` on a new pseudo-terminal the size of `panel`.
+    /// The panel stops treating LF as a newline, since the terminal sends
+    /// CR LF itself.
+    pub fn spawn(program: &str, args: &[&str], panel: &mut Panel) -> io::Result<Terminal>
+    {
+        let (master, slave) = open_pty()?;
+        set_window_size(&master, panel.char_width, panel.char_height)?;
+
+        let mut command = Command::new(program);
+        command.args(args)
+            .env("TERM", "xterm-256color")
+            .stdin(Stdio::from(slave.try_clone()?))
+            .stdout(Stdio::from(slave.try_clone()?))
+            .stderr(Stdio::from(slave));
+        // SAFETY: only async-signal-safe calls between fork and exec.
+        unsafe {
+            command.pre_exec(|| {
+                os_error(libc::setsid())?;
+                os_error(libc::ioctl(0, libc::TIOCSCTTY, 0))?;
+                Ok(())
+            });
+        }
+        let child = command.spawn()?;
+
+        let (sender, output) = mpsc::channel();
+        let mut reader = master.try_clone()?;
+        thread::spawn(move || {
+            let mut buf = [0; 4096];
+            // reading fails with EIO once the child closes the terminal
+            while let Ok(n) = reader.read(&mut buf) {
+                if n == 0 || sender.send(buf[..n].to_vec()).is_err() {
+                    break;
+                }
+            }
+        });
+
+        panel.lf_returns = false;
+        panel.show_cursor(true);
+        Ok(Terminal {
+            master,
+            child,
+            output,
+            parser: AnsiParser::new(panel.font_color.resolve(&panel.palette),
+                                    panel.back_color.map(|bg| bg.resolve(&panel.palette))),
+            closed: false,
+            cols: panel.char_width,
+            rows: panel.char_height,
+        })
+    }
+
+    /// Interprets everything the child has written since the last call
+    /// into `panel`, first telling the child if the panel changed size.
+    /// Returns whether any output arrived.
+    pub fn update(&mut self, panel: &mut Panel) -> bool
+    {
+        if (panel.char_width, panel.char_height) != (self.cols, self.rows) {
+            self.cols = panel.char_width;
+            self.rows = panel.char_height;
+            // the child's size is only a hint; drawing carries on if it fails
+            let _ = set_window_size(&self.master, self.cols, self.rows);
+        }
+
+        let mut changed = false;
+        loop {
+            match self.output.try_recv() {
+                Ok(bytes) => {
+                    self.parser.write(panel, &bytes);
+                    changed = true;
+                }
+                Err(TryRecvError::Disconnected) => {
+                    self.closed = true;
+                    break;
+                }
+                Err(TryRecvError::Empty) => break,
+            }
+        }
+        changed
+    }
+
+    /// True once the child has closed the terminal and `update` has
+    /// interpreted all of its output.
+    pub fn is_closed(&self) -> bool
+    {
+        self.closed
+    }
+
+    /// Sends raw bytes to the child, as if typed.
+    pub fn send(&mut self, bytes: &[u8]) -> io::Result<()>
+    {
+        self.master.write_all(bytes)
+    }
+
+    /// Sends this frame's keyboard input to the child: typed characters,
+    /// Ctrl+letter, and the usual escape sequences for editing and
+    /// cursor keys.
+    pub fn send_input(&mut self) -> io::Result<()>
+    {
+        let ctrl = is_key_down(KeyCode::LeftControl) || is_key_down(KeyCode::RightControl);
+        let mut bytes = vec![];
+
+        while let Some(c) = get_char_pressed() {
+            if ctrl && c.is_ascii_alphabetic() {
+                bytes.push(c.to_ascii_lowercase() as u8 & 0x1f);
+            } else if !c.is_control() {
+                bytes.extend_from_slice(c.encode_utf8(&mut [0; 4]).as_bytes());
+            }
+        }
+
+        let keys: [(KeyCode, &[u8]); 14] = [
+            (KeyCode::Enter, b"\r"),
+            (KeyCode::KpEnter, b"\r"),
+            (KeyCode::Backspace, b"\x7f"),
+            (KeyCode::Tab, b"\t"),
+            (KeyCode::Escape, b"\x1b"),
+            (KeyCode::Up, b"\x1b[A"),
+            (KeyCode::Down, b"\x1b[B"),
+            (KeyCode::Right, b"\x1b[C"),
+            (KeyCode::Left, b"\x1b[D"),
+            (KeyCode::Home, b"\x1b[H"),
+            (KeyCode::End, b"\x1b[F"),
+            (KeyCode::Delete, b"\x1b[3~"),
+            (KeyCode::PageUp, b"\x1b[5~"),
+            (KeyCode::PageDown, b"\x1b[6~"),
+        ];
+        for (key, sequence) in keys {
+            if is_key_pressed(key) {
+                bytes.extend_from_slice(sequence);
+            }
+        }
+
+        if bytes.is_empty() {
+            return Ok(());
+        }
+        self.send(&bytes)
+    }
+
+    /// True until the child process exits.
+    pub fn is_alive(&mut self) -> bool
+    {
+        matches!(self.child.try_wait(), Ok(None))
+    }
+}
+
+impl Drop for Terminal {
+    fn drop(&mut self)
+    {
+        let _ = self.child.kill();
+        let _ = self.child.wait();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::{Duration, Instant};
+
+    use super::*;
+    use crate::font::make_font;
+    use crate::font::tests::texture;
+    use crate::panel::Attrs;
+
+    #[test]
+    fn shell_output_is_interpreted()
+    {
+        let mut panel = Panel::builder(make_font(&texture(), 6, 8), 12, 3).font_color(WHITE).build();
+        let script = r"printf 'hello\nworld\033[1;10H\033[7mX\033[0m!'";
+        let mut term = Terminal::spawn("/bin/sh", &["-c", script], &mut panel).unwrap();
+
+        let deadline = Instant::now() + Duration::from_secs(10);
+        while !term.is_closed() {
+            assert!(Instant::now() < deadline, "no EOF from the shell");
+            term.update(&mut panel);
+            thread::sleep(Duration::from_millis(5));
+        }
+
+        // the terminal turns LF into CR LF
+        let rows: Vec<String> = panel.cells.iter()
+            .map(|row| row.iter().map(|cell| cell.ch).collect::<String>().trim_end().to_string())
+            .collect();
+        assert_eq!(rows, ["hello    X!", "world", ""]);
+        assert!(panel.cells[0][9].attrs.contains(Attrs::INVERSE));
+        assert!(!panel.cells[0][10].attrs.contains(Attrs::INVERSE));
+    }
+}